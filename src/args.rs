@@ -0,0 +1,43 @@
+/// The command line the game was started with. Flags take their value from the argument
+/// that follows them, as in `--seed 42`.
+#[derive(Debug, Clone, Default)]
+pub struct Args(Vec<String>);
+
+impl Args {
+    pub fn from_env() -> Self {
+        Args(std::env::args().collect())
+    }
+
+    pub fn has(&self, flag: &str) -> bool {
+        self.0.iter().any(|arg| arg == flag)
+    }
+
+    /// The argument following the first `flag`, if any.
+    pub fn value_of(&self, flag: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .position(|arg| arg == flag)
+            .and_then(|index| self.0.get(index + 1))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Args {
+        Args(line.split_whitespace().map(String::from).collect())
+    }
+
+    #[test]
+    fn flags_and_values() {
+        let args = args("game --headless --ticks 600 --pilot");
+        assert!(args.has("--headless"));
+        assert!(!args.has("--replay"));
+        assert_eq!(args.value_of("--ticks"), Some("600"));
+        // A flag at the very end has no value
+        assert_eq!(args.value_of("--pilot"), None);
+        assert_eq!(args.value_of("--seed"), None);
+    }
+}
@@ -1,4 +1,23 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+/// Building models of the space kit that stations are assembled from.
+pub const STATION_PARTS: [&str; 11] = [
+    "Base Large",
+    "Building L",
+    "Connector",
+    "Geodesic Dome",
+    "House Cylinder",
+    "House Long",
+    "House Open",
+    "House Pod",
+    "House Single Support",
+    "House Single",
+    "Metal Support",
+];
 
 #[derive(Resource, Debug, Default)]
 pub struct SpaceKit {
@@ -8,9 +27,32 @@ pub struct SpaceKit {
     pub planets: Vec<Handle<Scene>>,
     pub rock: Handle<Scene>,
     pub mechs: Vec<Handle<Scene>>,
+    /// Station parts by model name, see `STATION_PARTS`.
+    pub station_parts: HashMap<String, Handle<Scene>>,
     pub skybox: Handle<Scene>,
 }
 
+/// Reads a RON file into `T`. Errors name the file they came from.
+pub fn load_ron<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    ron::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Test fixture that writes `value` as RON to `name` in the temp directory, hands the path
+/// to `load` and removes the file again.
+#[cfg(test)]
+pub fn load_from_temp_file<T: serde::Serialize, R>(
+    value: &T,
+    name: &str,
+    load: impl FnOnce(&Path) -> Result<R, String>,
+) -> Result<R, String> {
+    let path = std::env::temp_dir().join(name);
+    fs::write(&path, ron::to_string(value).unwrap()).unwrap();
+    let loaded = load(&path);
+    let _ = fs::remove_file(&path);
+    loaded
+}
+
 pub struct AssetLoaderPlugin;
 
 impl Plugin for AssetLoaderPlugin {
@@ -45,6 +87,10 @@ fn load_assets(mut scene_assets: ResMut<SpaceKit>, asset_server: Res<AssetServer
             asset_server.load("Ultimate Space Kit-glb/Mech-D5wW2jDO42.glb#Scene0"),
             asset_server.load("Ultimate Space Kit-glb/Mech-o3Ps8z8ByP.glb#Scene0"),
         ],
+        station_parts: STATION_PARTS
+            .iter()
+            .map(|name| (name.to_string(), asset_server.load(format!("Ultimate Space Kit-glb/{}.glb#Scene0", name))))
+            .collect(),
         skybox: asset_server.load("skybox/galaxy_panorama.glb#Scene0"),
     }
 }
\ No newline at end of file
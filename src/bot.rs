@@ -0,0 +1,56 @@
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use crate::enemy::Enemy;
+use crate::game::AppState;
+use crate::input::{InputSet, PilotActions};
+use crate::mech::Mech;
+use crate::spaceship::SpaceShip;
+
+const FIRE_RANGE: f32 = 600.0;
+const FIRE_ALIGNMENT: f32 = 0.97;
+const CRUISE_DISTANCE: f32 = 150.0;
+const STEERING_GAIN: f32 = 4.0;
+
+/// Flies the ship by itself: turns towards the nearest hostile, closes in and shoots.
+/// Used to exercise the game without a human at the controls.
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, fly_bot.in_set(InputSet::Override).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn fly_bot(
+    mut actions: ResMut<PilotActions>,
+    ship_query: Query<&Transform, With<SpaceShip>>,
+    hostile_query: Query<&Transform, Or<(With<Enemy>, With<Mech>)>>,
+) {
+    *actions = PilotActions::default();
+    let Ok(ship_transform) = ship_query.get_single() else {
+        return;
+    };
+    let target = hostile_query
+        .iter()
+        .map(|transform| transform.translation)
+        .min_by(|a, b| {
+            a.distance_squared(ship_transform.translation)
+                .total_cmp(&b.distance_squared(ship_transform.translation))
+        });
+    let Some(target) = target else {
+        return;
+    };
+
+    let distance = target.distance(ship_transform.translation);
+    // The ship's nose is its local +Z axis
+    let local = ship_transform.rotation.inverse() * (target - ship_transform.translation).normalize_or_zero();
+    if local.z > 0.0 {
+        actions.pitch = (-local.y * STEERING_GAIN).clamp(-1.0, 1.0);
+        actions.yaw = (local.x * STEERING_GAIN).clamp(-1.0, 1.0);
+    } else {
+        // Target is behind us, turn as hard as possible
+        actions.yaw = if local.x >= 0.0 { 1.0 } else { -1.0 };
+    }
+    actions.thrust = if distance > CRUISE_DISTANCE { 1.0 } else { 0.0 };
+    actions.fire = local.z > FIRE_ALIGNMENT && distance < FIRE_RANGE;
+}
@@ -1,93 +1,322 @@
-use crate::mech::Mech;
+use crate::faction::{Faction, FactionRelations};
+use crate::health::{DamageDealt, Health};
 use bevy::app::{App, Plugin};
 use bevy::asset::{AssetContainer, Assets};
 use bevy_rapier3d::dynamics::{GravityScale, RigidBody};
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::time::Duration;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_rapier3d::prelude::*;
 use crate::camera::MainCamera;
+use crate::game::{reset_resource, AppState, InGame};
+use crate::weapon::{WeaponDefinition, WeaponDefinitions};
 
 #[derive(Component, Debug)]
-pub struct Bullet(Timer);
+pub struct Bullet {
+    timer: Timer,
+    pub owner: Entity,
+    pub faction: Faction,
+    pub damage: f32,
+    /// False while the bullet waits in the pool.
+    active: bool,
+}
 
+/// A single bullet impact. `damage` is zero when the hit was blocked by faction relations
+/// or the target cannot take damage.
 #[derive(Event, Debug)]
 pub struct BulletHit {
-    pub count: u32
+    pub bullet: Entity,
+    pub target: Entity,
+    pub shooter: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub damage: f32,
+}
+
+/// Mesh and material shared by every projectile of one weapon.
+#[derive(Debug, Clone)]
+pub struct BulletVisual {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Projectile visuals of every weapon, created once so firing never adds assets.
+#[derive(Resource, Debug, Default)]
+pub struct BulletAssets {
+    visuals: HashMap<String, BulletVisual>,
+}
+
+impl BulletAssets {
+    pub fn get(&self, weapon: &str) -> Option<&BulletVisual> {
+        self.visuals.get(weapon)
+    }
+}
+
+/// Bullets that hit something or expired. They stay in the world, hidden and taken out of
+/// the physics simulation, until they are fired again.
+#[derive(Resource, Debug, Default)]
+pub struct BulletPool {
+    free: Vec<Entity>,
+}
+
+/// Collision group of every bullet. Bullets leave it out of their filter so pellets fired
+/// side by side never hit each other.
+const BULLET_GROUP: Group = Group::GROUP_2;
+
+/// Query filter for rays that should only stop at solid objects: it skips `exclude`,
+/// sensors and bullets.
+pub fn solid_ray_filter(exclude: Entity) -> QueryFilter<'static> {
+    QueryFilter::default()
+        .exclude_collider(exclude)
+        .exclude_sensors()
+        .groups(CollisionGroups::new(Group::ALL, !BULLET_GROUP))
+}
+
+/// Systems that take bullets out of play run in `Release`, before anything in `Fire` can
+/// take a pooled bullet and fire it again in the same tick.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BulletSet {
+    Release,
+    Fire,
 }
 
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (detect_collision, despawn_bullet))
+        app.init_resource::<BulletAssets>()
+            .init_resource::<BulletPool>()
+            .add_systems(Startup, create_bullet_assets)
+            .add_systems(OnExit(InGame), reset_resource::<BulletPool>)
+            .configure_sets(Update, BulletSet::Release.before(BulletSet::Fire))
+            .add_systems(Update, (detect_collision, despawn_bullet).in_set(BulletSet::Release).run_if(in_state(AppState::Playing)))
             .add_event::<BulletHit>();
     }
 }
 
+fn create_bullet_assets(
+    weapons: Res<WeaponDefinitions>,
+    mut bullet_assets: ResMut<BulletAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (name, weapon) in weapons.weapons.iter() {
+        let (red, green, blue) = weapon.visual.color;
+        let (glow_red, glow_green, glow_blue) = weapon.visual.glow;
+        let material = materials.add(StandardMaterial {
+            base_color: Color::srgba(red, green, blue, 0.9), // Semi-transparent
+            emissive: LinearRgba::from(Color::srgb(glow_red, glow_green, glow_blue)),
+            alpha_mode: AlphaMode::Add,                 // Additive blending
+            unlit: true,
+            ..default()
+        });
+        let mesh = meshes.add(Capsule3d {
+            radius: weapon.visual.radius,
+            half_length: 0.05,
+        });
+        bullet_assets.visuals.insert(name.clone(), BulletVisual { mesh, material });
+    }
+}
+
+/// Fires a bullet, reusing one from the pool when there is one.
 pub fn spawn_bullet(
-    mut commands: &mut Commands,
-    mut meshes: &mut ResMut<Assets<Mesh>>,
-    mut materials: &mut ResMut<Assets<StandardMaterial>>,
+    commands: &mut Commands,
+    pool: &mut BulletPool,
+    visual: &BulletVisual,
     velocity: Velocity,
     transform: Transform,
+    owner: Entity,
+    faction: Faction,
+    weapon: &WeaponDefinition,
 ) {
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgba(1.0, 0.0, 0.0, 0.9), // Semi-transparent red
-        emissive: LinearRgba::from(Color::srgb(10.0, 0.1, 0.1)),        // Intense red glow
-        alpha_mode: AlphaMode::Add,                 // Additive blending
-        unlit: true,
-        ..default()
-    });
-    commands.spawn((
+    let bundle = (
         transform,
         velocity,
+        Mesh3d(visual.mesh.clone()),
+        MeshMaterial3d(visual.material.clone()),
+        Visibility::Visible,
+        Bullet {
+            timer: Timer::new(Duration::from_secs_f32(weapon.lifetime), TimerMode::Once),
+            owner,
+            faction,
+            damage: weapon.damage,
+            active: true,
+        },
+    );
+    if let Some(entity) = pool.free.pop() {
+        commands.entity(entity)
+            .insert(bundle)
+            .remove::<(RigidBodyDisabled, ColliderDisabled)>();
+        return;
+    }
+    commands.spawn((
+        bundle,
         RigidBody::Dynamic,
-        Mesh3d(meshes.add(Capsule3d {
-            radius: 0.3,
-            half_length: 0.05,
-        })),
         Collider::capsule_z(1.0, 1.0),
-        MeshMaterial3d(material),
         GravityScale(0.0),
-        Bullet(Timer::new(Duration::from_secs(5), TimerMode::Once)),
+        CollisionGroups::new(BULLET_GROUP, !BULLET_GROUP),
+        StateScoped(InGame),
     )).insert(ActiveEvents::COLLISION_EVENTS);
 }
 
+/// Hides a bullet and takes it out of the simulation until it is fired again.
+fn release_bullet(commands: &mut Commands, pool: &mut BulletPool, entity: Entity, bullet: &mut Bullet) {
+    if !bullet.active {
+        return;
+    }
+    bullet.active = false;
+    commands.entity(entity).insert((Visibility::Hidden, RigidBodyDisabled, ColliderDisabled));
+    pool.free.push(entity);
+}
+
 fn detect_collision(
+    mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     mut bullet_hit_events: EventWriter<BulletHit>,
-    bullet_query: Query<Entity, With<Bullet>>,
-    target_query: Query<Entity, With<Mech>>,
+    mut damage_events: EventWriter<DamageDealt>,
+    relations: Res<FactionRelations>,
+    rapier_context: ReadRapierContext,
+    mut pool: ResMut<BulletPool>,
+    mut bullet_query: Query<(&mut Bullet, &Transform)>,
+    // Sensors, such as missiles, and other bullets don't stop bullets
+    target_query: Query<(Option<&Faction>, Has<Health>, &Transform), (Without<Sensor>, Without<Bullet>)>,
 ) {
-    let bullets: HashSet<Entity> = bullet_query.iter().collect();
-    let targets: HashSet<Entity> = target_query.iter().collect();
-    let mut hit_count: u32 = 0;
+    let rapier_context = rapier_context.single();
     collision_events.read().for_each(|event| match event {
-        CollisionEvent::Started(e1, e2, flags) => {
-            let bullet_involved = bullets.contains(e1) || bullets.contains(e2);
-            let mech_involved = targets.contains(e1) || targets.contains(e2);
-            if bullet_involved && mech_involved {
-                println!("Bullet is involved the collision.");
-                hit_count += 1;
+        CollisionEvent::Started(e1, e2, _) => {
+            let (bullet_entity, target) = if bullet_query.contains(*e1) {
+                (*e1, *e2)
+            } else if bullet_query.contains(*e2) {
+                (*e2, *e1)
+            } else {
+                return;
+            };
+            let (mut bullet, bullet_transform) = bullet_query.get_mut(bullet_entity).unwrap();
+            // A bullet released earlier this tick can still report contacts
+            if target == bullet.owner || !bullet.active {
+                return;
             }
+            let Ok((target_faction, has_health, target_transform)) = target_query.get(target) else {
+                return;
+            };
+
+            let (point, normal) = contact_point(&rapier_context, bullet_entity, target)
+                .unwrap_or((bullet_transform.translation, Vec3::ZERO));
+            // Make the normal point from the target's surface back towards the bullet
+            let outward = bullet_transform.translation - target_transform.translation;
+            let normal = if normal == Vec3::ZERO {
+                outward.normalize_or_zero()
+            } else if normal.dot(outward) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
 
+            let damage = if relations.allows(bullet.faction, target_faction) && has_health { bullet.damage } else { 0.0 };
+            if damage > 0.0 {
+                damage_events.send(DamageDealt {
+                    target,
+                    amount: damage,
+                    source: Some(bullet.owner),
+                });
+            }
+            bullet_hit_events.send(BulletHit {
+                bullet: bullet_entity,
+                target,
+                shooter: bullet.owner,
+                point,
+                normal,
+                damage,
+            });
+            release_bullet(&mut commands, &mut pool, bullet_entity, &mut bullet);
         },
         CollisionEvent::Stopped(_, _, _) => {}
     });
-    bullet_hit_events.send(BulletHit { count: hit_count });
+}
+
+/// World-space contact point and normal of the first solver contact between two colliders.
+pub fn contact_point(rapier_context: &RapierContext, e1: Entity, e2: Entity) -> Option<(Vec3, Vec3)> {
+    let contact_pair = rapier_context.contact_pair(e1, e2)?;
+    let contact = contact_pair.manifolds().find_map(|manifold| {
+        manifold
+            .solver_contacts()
+            .next()
+            .map(|contact| (contact.point(), manifold.normal()))
+    });
+    contact
 }
 
 fn despawn_bullet(
     mut commands: Commands,
     time: Res<Time>,
+    mut pool: ResMut<BulletPool>,
     mut bullet_query: Query<(Entity, &mut Bullet), With<Bullet>>,) {
-    bullet_query.iter_mut().for_each(|(entity,mut bullet)| {
-        let mut timer = &mut bullet.0;
-        if timer.tick(time.delta()).just_finished() {
-            commands.entity(entity).despawn_recursive();
-            //println!("Despawned bullet entity.");
+    bullet_query.iter_mut().filter(|(_, bullet)| bullet.active).for_each(|(entity,mut bullet)| {
+        if bullet.timer.tick(time.delta()).just_finished() {
+            release_bullet(&mut commands, &mut pool, entity, &mut bullet);
         };
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::{fire_weapon, WEAPONS_PATH};
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::scene::ScenePlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::path::Path;
+
+    #[test]
+    fn scatter_gun_pellets_survive_the_first_physics_step() {
+        let weapons = WeaponDefinitions::load(Path::new(WEAPONS_PATH)).unwrap();
+        let scatter_gun = weapons.get("Scatter Gun").unwrap().clone();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins((TransformPlugin, HierarchyPlugin, AssetPlugin::default(), ScenePlugin))
+            .init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(1.0 / 60.0)))
+            .init_resource::<BulletPool>()
+            .init_resource::<FactionRelations>()
+            .add_event::<BulletHit>()
+            .add_event::<DamageDealt>()
+            .add_systems(Update, detect_collision);
+        app.finish();
+        app.cleanup();
+        app.update();
+
+        let shooter = app.world_mut().spawn_empty().id();
+        let shots = scatter_gun.muzzles.len();
+        app.world_mut().run_system_once(move |mut commands: Commands, mut pool: ResMut<BulletPool>| {
+            let visual = BulletVisual {
+                mesh: Handle::default(),
+                material: Handle::default(),
+            };
+            fire_weapon(
+                &mut commands,
+                &mut pool,
+                &visual,
+                &mut StdRng::seed_from_u64(0),
+                &scatter_gun,
+                shooter,
+                Faction::Player,
+                Vec3::ZERO,
+                Quat::IDENTITY,
+                None,
+            );
+        }).unwrap();
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let world = app.world_mut();
+        let active = world.query::<&Bullet>().iter(world).filter(|bullet| bullet.active).count();
+        assert_eq!(active, shots);
+        assert!(world.resource::<BulletPool>().free.is_empty());
+    }
+}
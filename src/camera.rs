@@ -1,9 +1,9 @@
 use crate::spaceship::SpaceShip;
-use bevy::pbr::{CascadeShadowConfigBuilder, NotShadowCaster};
+use bevy::pbr::NotShadowCaster;
 use bevy::prelude::*;
-use std::f32::consts::PI;
 use bevy_rapier3d::dynamics::{GravityScale, RigidBody};
 use crate::asset::SpaceKit;
+use crate::game::InGame;
 use bevy::render::camera::Viewport;
 
 const CAMERA_DISTANCE: f32 = 80.0;
@@ -16,34 +16,11 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, (spawn_camera, spawn_skybox));
-        app.add_systems(Update, follow_spaceship_smooth);
+        app.add_systems(Update, follow_spaceship_smooth.run_if(in_state(InGame)));
     }
 }
 
 fn spawn_camera(mut commands: Commands) {
-    // Add a camera so we can see the debug-render.
-    // directional 'sun' light
-    commands.spawn((
-        DirectionalLight {
-            illuminance: light_consts::lux::AMBIENT_DAYLIGHT,
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform {
-            translation: Vec3::new(0.0, 10.0, 15.),
-            rotation: Quat::from_rotation_x(-PI / 4.),
-            ..default()
-        },
-        // The default cascade config is designed to handle large scenes.
-        // As this example has a much smaller world, we can tighten the shadow
-        // bounds for better visual quality.
-        CascadeShadowConfigBuilder {
-            first_cascade_far_bound: 4.0,
-            maximum_distance: 10.0,
-            ..default()
-        }
-        .build(),
-    ));
     commands.spawn((
         Camera3d::default(),
         Transform::from_xyz(0., 10.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
@@ -4,6 +4,7 @@ use bevy::color::palettes::css::WHITE;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use crate::asset::SpaceKit;
+use crate::game::{AppState, InGame};
 
 pub struct CrossHairPlugin;
 
@@ -12,8 +13,8 @@ pub struct CrossHair;
 
 impl Plugin for CrossHairPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_crosshair)
-            .add_systems(Update, update_crosshair_position);
+        app.add_systems(OnEnter(InGame), spawn_crosshair)
+            .add_systems(Update, update_crosshair_position.run_if(in_state(AppState::Playing)));
     }
 }
 
@@ -35,6 +36,7 @@ fn spawn_crosshair(
         MeshMaterial3d(material.clone()),
         Transform::from_xyz(0.0, 0.0, 17.0),
         CrossHair,
+        StateScoped(InGame),
         ));
 }
 fn update_crosshair_position(
@@ -1,75 +1,559 @@
-use std::time::Duration;
+use std::collections::HashMap;
 use bevy::app::App;
 use bevy::prelude::*;
-use rand::random_range;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use crate::bullet::BulletHit;
 use crate::camera::MainCamera;
-use crate::spaceship::SpaceshipThrusted;
+use crate::game::{reset_resource, AppState, InGame};
+use crate::impact::Impact;
+use crate::explosion::Explosion;
+use crate::rng::{random_direction, GameRng, RngStream};
+use crate::spaceship::{SpaceShip, SpaceshipThrusted};
 
-#[derive(Component)]
-pub struct SpaceshipTrail {
-    timer: Timer
+/// Number of materials each style fades through over a particle's lifetime.
+const RAMP_STEPS: usize = 8;
+/// Live particles are capped so a large battle can't flood the world with quads.
+const MAX_PARTICLES: usize = 5000;
+const FLASH_DURATION: f32 = 0.15;
+const FLASH_LIGHT_INTENSITY: f32 = 2_000_000.0;
+const SHOCKWAVE_DURATION: f32 = 0.6;
+/// Radius a shockwave grows to, in multiples of the size of what exploded.
+const SHOCKWAVE_GROWTH: f32 = 8.0;
+
+/// Colour scheme of a particle, from when it is emitted to when it dies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParticleStyle {
+    Fire,
+    Sparks,
+    Smoke,
+    Debris,
+    Flash,
+    Shockwave,
+}
+
+impl ParticleStyle {
+    const ALL: [ParticleStyle; 6] = [
+        ParticleStyle::Fire,
+        ParticleStyle::Sparks,
+        ParticleStyle::Smoke,
+        ParticleStyle::Debris,
+        ParticleStyle::Flash,
+        ParticleStyle::Shockwave,
+    ];
+
+    /// Start and end colour. Alpha fades the particle out, colour components above 1 glow.
+    fn colors(&self) -> (LinearRgba, LinearRgba) {
+        match self {
+            ParticleStyle::Fire => (LinearRgba::new(4.0, 2.0, 0.2, 0.8), LinearRgba::new(1.0, 0.1, 0.0, 0.0)),
+            ParticleStyle::Sparks => (LinearRgba::new(6.0, 5.0, 2.0, 1.0), LinearRgba::new(2.0, 0.5, 0.0, 0.0)),
+            ParticleStyle::Smoke => (LinearRgba::new(0.4, 0.4, 0.4, 0.5), LinearRgba::new(0.1, 0.1, 0.1, 0.0)),
+            ParticleStyle::Debris => (LinearRgba::new(0.6, 0.5, 0.4, 1.0), LinearRgba::new(0.2, 0.2, 0.2, 0.0)),
+            ParticleStyle::Flash => (LinearRgba::new(10.0, 9.0, 6.0, 1.0), LinearRgba::new(4.0, 2.0, 0.5, 0.0)),
+            ParticleStyle::Shockwave => (LinearRgba::new(1.0, 1.5, 2.5, 0.4), LinearRgba::new(0.2, 0.4, 1.0, 0.0)),
+        }
+    }
+}
+
+/// Emits camera-facing particles from wherever its entity is. Continuous emitters emit
+/// `rate` particles per second while `active`; a `ParticleBurst` event emits `burst` at once.
+#[derive(Component, Debug, Clone)]
+pub struct ParticleEmitter {
+    pub style: ParticleStyle,
+    pub rate: f32,
+    pub burst: u32,
+    /// Seconds each particle lives.
+    pub lifetime: f32,
+    /// Velocity of new particles in the emitter's local space.
+    pub velocity: Vec3,
+    /// Random speed added to each particle in any direction.
+    pub jitter: f32,
+    /// Fraction of velocity a particle keeps per second.
+    pub drag: f32,
+    /// Size at birth and at death, in world units.
+    pub size: (f32, f32),
+    pub active: bool,
+    accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(style: ParticleStyle, lifetime: f32, size: (f32, f32)) -> Self {
+        ParticleEmitter {
+            style,
+            rate: 0.0,
+            burst: 0,
+            lifetime,
+            velocity: Vec3::ZERO,
+            jitter: 0.0,
+            drag: 1.0,
+            size,
+            active: false,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Exhaust streaming out behind a ship, whose nose is its local +Z axis.
+    pub fn engine_trail() -> Self {
+        ParticleEmitter {
+            rate: 120.0,
+            velocity: Vec3::new(0.0, 0.0, -20.0),
+            jitter: 2.0,
+            ..ParticleEmitter::new(ParticleStyle::Fire, 1.0, (0.6, 0.1))
+        }
+    }
+
+    /// Sparks thrown off along the emitter's +Z axis, e.g. a surface normal.
+    pub fn impact_sparks() -> Self {
+        ParticleEmitter {
+            burst: 12,
+            velocity: Vec3::new(0.0, 0.0, 15.0),
+            jitter: 12.0,
+            drag: 0.2,
+            ..ParticleEmitter::new(ParticleStyle::Sparks, 0.4, (0.4, 0.05))
+        }
+    }
+
+    /// Fireball of an object of roughly `scale` units across.
+    pub fn explosion(scale: f32) -> Self {
+        ParticleEmitter {
+            burst: (20.0 * scale.sqrt()) as u32,
+            jitter: 8.0 * scale,
+            drag: 0.1,
+            ..ParticleEmitter::new(ParticleStyle::Fire, 0.8, (1.5 * scale, 3.0 * scale))
+        }
+    }
+
+    pub fn smoke(scale: f32) -> Self {
+        ParticleEmitter {
+            burst: (10.0 * scale.sqrt()) as u32,
+            jitter: 3.0 * scale,
+            drag: 0.3,
+            ..ParticleEmitter::new(ParticleStyle::Smoke, 2.5, (1.0 * scale, 4.0 * scale))
+        }
+    }
+
+    /// Small fragments flying apart.
+    pub fn debris(scale: f32) -> Self {
+        ParticleEmitter {
+            burst: (8.0 * scale.sqrt()) as u32,
+            jitter: 15.0 * scale,
+            drag: 0.8,
+            ..ParticleEmitter::new(ParticleStyle::Debris, 2.0, (0.3 * scale, 0.2 * scale))
+        }
+    }
+}
+
+/// Emits an emitter's `burst` once at the given place, without keeping an emitter around.
+#[derive(Event, Debug, Clone)]
+pub struct ParticleBurst {
+    pub emitter: ParticleEmitter,
+    pub transform: Transform,
+}
+
+#[derive(Component, Debug)]
+struct Particle {
+    age: f32,
+    lifetime: f32,
+    velocity: Vec3,
+    drag: f32,
+    size: (f32, f32),
+    style: ParticleStyle,
+    step: usize,
+}
+
+/// A flash or shockwave sphere growing from `radius.0` to `radius.1` as it fades out.
+#[derive(Component, Debug)]
+struct ExplosionShell {
+    age: f32,
+    lifetime: f32,
+    radius: (f32, f32),
+    style: ParticleStyle,
+    step: usize,
+}
+
+/// A tumbling fragment of something that blew up, fading out over its lifetime.
+#[derive(Component, Debug)]
+struct DebrisPiece {
+    age: f32,
+    lifetime: f32,
+    step: usize,
+}
+
+/// Meshes and a colour ramp per style, shared by every particle, shell and debris piece.
+#[derive(Resource, Debug, Default)]
+struct ParticleAssets {
+    quad: Handle<Mesh>,
+    sphere: Handle<Mesh>,
+    chunk: Handle<Mesh>,
+    ramps: HashMap<ParticleStyle, Vec<Handle<StandardMaterial>>>,
+}
+
+impl ParticleAssets {
+    fn material(&self, style: ParticleStyle, progress: f32) -> (usize, Handle<StandardMaterial>) {
+        let step = ((progress * RAMP_STEPS as f32) as usize).min(RAMP_STEPS - 1);
+        (step, self.ramps[&style][step].clone())
+    }
+}
+
+/// Dead particles, hidden and waiting to be emitted again.
+#[derive(Resource, Debug, Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+    live: usize,
 }
 
+#[derive(Component)]
+struct EngineTrail;
+
 pub struct EffectsPlugin;
 
 impl Plugin for EffectsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (spawn_trail, update_trails));
+        app.init_resource::<ParticleAssets>()
+            .init_resource::<ParticlePool>()
+            .add_event::<ParticleBurst>()
+            .add_systems(Startup, create_particle_assets)
+            .add_systems(OnExit(InGame), reset_resource::<ParticlePool>)
+            .add_systems(Update, (
+                (attach_engine_trail, toggle_engine_trail, spark_on_bullet_hit, spark_on_impact, draw_explosions),
+                (run_emitters, emit_bursts, update_particles, update_explosions).chain(),
+            ).chain().run_if(in_state(AppState::Playing)));
     }
 }
 
-pub fn spawn_trail(
-    mut commands: Commands,
+fn create_particle_assets(
+    mut particle_assets: ResMut<ParticleAssets>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    camera_query: Single<(&Transform), With<MainCamera>>,
-    mut event_reader: EventReader<SpaceshipThrusted>) {
-
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgba(1.0, 0.5, 0.0, 0.6),
-        emissive: LinearRgba::from(Color::srgb(4.0, 2.0, 0.0)),
-        alpha_mode: AlphaMode::Add, // For glowing blend
-        cull_mode: None,
-        ..default()
-    });
-    event_reader.read().for_each(|event| {
-        for _ in 1..3 {
-            let ship_transform = event.ship_transform;
-            let forward = ship_transform.forward(); // -Z in Bevy by default
-            let right = ship_transform.right();     // +X
-            let up = ship_transform.up();           // +Y
-
-            // Randomize offset in local space
-            let offset = right * random_range(-0.2..0.2)
-                + up * random_range(-0.2..0.2)
-                - forward * random_range(-7.0..-5.0); // behind the ship
-
-            let back_position = ship_transform.translation + offset;
+) {
+    particle_assets.quad = meshes.add(Rectangle::new(1.0, 1.0));
+    particle_assets.sphere = meshes.add(Sphere::new(1.0).mesh().ico(3).unwrap());
+    particle_assets.chunk = meshes.add(Cuboid::new(1.0, 1.0, 1.0));
+    for style in ParticleStyle::ALL {
+        let (start, end) = style.colors();
+        let ramp = (0..RAMP_STEPS)
+            .map(|step| {
+                let color = start.mix(&end, step as f32 / (RAMP_STEPS - 1) as f32);
+                materials.add(StandardMaterial {
+                    base_color: Color::LinearRgba(color),
+                    emissive: LinearRgba::rgb(color.red * color.alpha, color.green * color.alpha, color.blue * color.alpha),
+                    alpha_mode: AlphaMode::Add, // For glowing blend
+                    unlit: true,
+                    cull_mode: None,
+                    ..default()
+                })
+            })
+            .collect();
+        particle_assets.ramps.insert(style, ramp);
+    }
+}
+
+fn attach_engine_trail(mut commands: Commands, ship_query: Query<Entity, Added<SpaceShip>>) {
+    for ship in ship_query.iter() {
+        commands.entity(ship).with_children(|parent| {
+            parent.spawn((
+                Transform::from_xyz(0.0, 0.5, -6.0),
+                ParticleEmitter::engine_trail(),
+                EngineTrail,
+            ));
+        });
+    }
+}
+
+fn toggle_engine_trail(
+    mut thrusted_events: EventReader<SpaceshipThrusted>,
+    mut trail_query: Query<&mut ParticleEmitter, With<EngineTrail>>,
+) {
+    let thrusting = thrusted_events.read().count() > 0;
+    for mut emitter in trail_query.iter_mut() {
+        emitter.active = thrusting;
+    }
+}
+
+fn spark_on_bullet_hit(mut bullet_hit_events: EventReader<BulletHit>, mut burst_events: EventWriter<ParticleBurst>) {
+    for hit in bullet_hit_events.read() {
+        let normal = hit.normal.normalize_or(Vec3::Y);
+        burst_events.send(ParticleBurst {
+            emitter: ParticleEmitter::impact_sparks(),
+            transform: Transform::from_translation(hit.point).with_rotation(Quat::from_rotation_arc(Vec3::Z, normal)),
+        });
+    }
+}
+
+/// Sparks flying off bodies scraping or slamming into each other, more for harder hits.
+fn spark_on_impact(mut impact_events: EventReader<Impact>, mut burst_events: EventWriter<ParticleBurst>) {
+    for impact in impact_events.read() {
+        let normal = impact.normal.normalize_or(Vec3::Y);
+        let emitter = ParticleEmitter::impact_sparks();
+        burst_events.send(ParticleBurst {
+            emitter: ParticleEmitter {
+                burst: if impact.damage > 0.0 { emitter.burst * 2 + impact.damage as u32 } else { emitter.burst / 3 },
+                jitter: emitter.jitter * 1.5,
+                ..emitter
+            },
+            // Sparks are sprayed sideways along the scraping surfaces
+            transform: Transform::from_translation(impact.point)
+                .with_rotation(Quat::from_rotation_arc(Vec3::Z, normal.any_orthonormal_vector())),
+        });
+    }
+}
+
+/// Flash, shockwave, sparks, smoke and tumbling debris, all scaled by the size of what
+/// exploded.
+fn draw_explosions(
+    mut commands: Commands,
+    particle_assets: Res<ParticleAssets>,
+    mut rng: ResMut<GameRng>,
+    mut explosion_events: EventReader<Explosion>,
+    mut burst_events: EventWriter<ParticleBurst>,
+) {
+    let rng = rng.stream(RngStream::Effects);
+    for explosion in explosion_events.read() {
+        let size = explosion.size;
+        let transform = Transform::from_translation(explosion.position);
+        for emitter in [ParticleEmitter::explosion(size), ParticleEmitter::smoke(size), ParticleEmitter::debris(size)] {
+            burst_events.send(ParticleBurst { emitter, transform });
+        }
+        burst_events.send(ParticleBurst {
+            emitter: ParticleEmitter {
+                burst: (30.0 * size.sqrt()) as u32,
+                jitter: 40.0 * size,
+                ..ParticleEmitter::impact_sparks()
+            },
+            transform,
+        });
 
+        for (style, lifetime, radius) in [
+            (ParticleStyle::Flash, FLASH_DURATION, (size * 1.5, size * 3.0)),
+            (ParticleStyle::Shockwave, SHOCKWAVE_DURATION, (size, size * SHOCKWAVE_GROWTH)),
+        ] {
             commands.spawn((
-                Mesh3d(meshes.add(Mesh::from(Rectangle::new(0.2, 0.2)))),
-                MeshMaterial3d(material.clone()),
-                Transform {
-                    translation: back_position,
-                    scale: Vec3::splat(random_range(0.2..0.5) as f32),
-                    ..default()
-                }.looking_at(camera_query.translation, Vec3::Z),
-                SpaceshipTrail {
-                    timer: Timer::new(Duration::from_secs(1), TimerMode::Once),
+                Mesh3d(particle_assets.sphere.clone()),
+                MeshMaterial3d(particle_assets.ramps[&style][0].clone()),
+                transform.with_scale(Vec3::splat(radius.0)),
+                ExplosionShell {
+                    age: 0.0,
+                    lifetime,
+                    radius,
+                    style,
+                    step: 0,
                 },
+                StateScoped(InGame),
             ));
         }
-    });
+        commands.spawn((
+            transform,
+            PointLight {
+                color: Color::srgb(1.0, 0.7, 0.3),
+                intensity: FLASH_LIGHT_INTENSITY * size,
+                range: size * 60.0,
+                ..default()
+            },
+            ExplosionShell {
+                age: 0.0,
+                lifetime: FLASH_DURATION,
+                radius: (0.0, 0.0),
+                style: ParticleStyle::Flash,
+                step: 0,
+            },
+            StateScoped(InGame),
+        ));
+
+        let pieces = ((size * 3.0) as usize).clamp(3, 12);
+        for _ in 0..pieces {
+            let direction = random_direction(rng);
+            let piece_size = size * rng.random_range(0.15..0.4);
+            commands.spawn((
+                Mesh3d(particle_assets.chunk.clone()),
+                MeshMaterial3d(particle_assets.ramps[&ParticleStyle::Debris][0].clone()),
+                Transform::from_translation(explosion.position + direction * size * 0.5)
+                    .with_scale(Vec3::splat(piece_size)),
+                RigidBody::Dynamic,
+                // Debris only tumbles, it never pushes anything around
+                Collider::cuboid(0.5, 0.5, 0.5),
+                CollisionGroups::new(Group::NONE, Group::NONE),
+                GravityScale(0.0),
+                Damping {
+                    linear_damping: 0.3,
+                    angular_damping: 0.1,
+                },
+                Velocity {
+                    linvel: direction * rng.random_range(10.0..30.0) * size.sqrt(),
+                    angvel: Vec3::new(
+                        rng.random_range(-5.0..5.0),
+                        rng.random_range(-5.0..5.0),
+                        rng.random_range(-5.0..5.0),
+                    ),
+                },
+                DebrisPiece {
+                    age: 0.0,
+                    lifetime: rng.random_range(2.0..4.0),
+                    step: 0,
+                },
+                StateScoped(InGame),
+            ));
+        }
+    }
+}
+
+fn update_explosions(
+    mut commands: Commands,
+    time: Res<Time>,
+    particle_assets: Res<ParticleAssets>,
+    mut shell_query: Query<(Entity, &mut ExplosionShell, &mut Transform, Option<&mut MeshMaterial3d<StandardMaterial>>, Option<&mut PointLight>)>,
+    mut debris_query: Query<(Entity, &mut DebrisPiece, &mut MeshMaterial3d<StandardMaterial>), Without<ExplosionShell>>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut shell, mut transform, material, light) in shell_query.iter_mut() {
+        shell.age += delta;
+        if shell.age >= shell.lifetime {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = shell.age / shell.lifetime;
+        // Ease out so the shockwave shoots out fast and then slows down
+        let eased = 1.0 - (1.0 - progress).powi(3);
+        transform.scale = Vec3::splat(shell.radius.0.lerp(shell.radius.1, eased));
+        if let Some(mut light) = light {
+            light.intensity *= 1.0 - progress;
+        }
+        let (step, handle) = particle_assets.material(shell.style, progress);
+        if let Some(mut material) = material {
+            if step != shell.step {
+                shell.step = step;
+                material.0 = handle;
+            }
+        }
+    }
+
+    for (entity, mut piece, mut material) in debris_query.iter_mut() {
+        piece.age += delta;
+        if piece.age >= piece.lifetime {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let (step, handle) = particle_assets.material(ParticleStyle::Debris, piece.age / piece.lifetime);
+        if step != piece.step {
+            piece.step = step;
+            material.0 = handle;
+        }
+    }
 }
 
-fn update_trails(
+fn run_emitters(
     mut commands: Commands,
-    mut query: Query<(Entity,&mut SpaceshipTrail)>,
-    time: Res<Time>) {
-    for (entity, mut trail) in query.iter_mut() {
-        trail.timer.tick(time.delta());
-        if trail.timer.just_finished() {
-            commands.entity(entity).despawn();
+    time: Res<Time>,
+    particle_assets: Res<ParticleAssets>,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<GameRng>,
+    mut emitter_query: Query<(&mut ParticleEmitter, &GlobalTransform)>,
+) {
+    let rng = rng.stream(RngStream::Effects);
+    for (mut emitter, global_transform) in emitter_query.iter_mut() {
+        if !emitter.active {
+            emitter.accumulator = 0.0;
+            continue;
+        }
+        emitter.accumulator += emitter.rate * time.delta_secs();
+        let count = emitter.accumulator as u32;
+        emitter.accumulator -= count as f32;
+        let transform = global_transform.compute_transform();
+        emit(&mut commands, &particle_assets, &mut pool, rng, &emitter, &transform, count);
+    }
+}
+
+fn emit_bursts(
+    mut commands: Commands,
+    particle_assets: Res<ParticleAssets>,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<GameRng>,
+    mut burst_events: EventReader<ParticleBurst>,
+) {
+    let rng = rng.stream(RngStream::Effects);
+    for burst in burst_events.read() {
+        emit(&mut commands, &particle_assets, &mut pool, rng, &burst.emitter, &burst.transform, burst.emitter.burst);
+    }
+}
+
+fn emit(
+    commands: &mut Commands,
+    particle_assets: &ParticleAssets,
+    pool: &mut ParticlePool,
+    rng: &mut impl Rng,
+    emitter: &ParticleEmitter,
+    transform: &Transform,
+    count: u32,
+) {
+    let material = particle_assets.ramps[&emitter.style][0].clone();
+    for _ in 0..count {
+        if pool.live >= MAX_PARTICLES {
+            return;
+        }
+        pool.live += 1;
+        let jitter = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        ) * emitter.jitter;
+        let bundle = (
+            Transform::from_translation(transform.translation).with_scale(Vec3::splat(emitter.size.0)),
+            MeshMaterial3d(material.clone()),
+            Visibility::Visible,
+            Particle {
+                age: 0.0,
+                lifetime: emitter.lifetime,
+                velocity: transform.rotation * emitter.velocity + jitter,
+                drag: emitter.drag,
+                size: emitter.size,
+                style: emitter.style,
+                step: 0,
+            },
+        );
+        match pool.free.pop() {
+            Some(entity) => {
+                commands.entity(entity).insert(bundle);
+            }
+            None => {
+                commands.spawn((bundle, Mesh3d(particle_assets.quad.clone()), StateScoped(InGame)));
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+fn update_particles(
+    time: Res<Time>,
+    particle_assets: Res<ParticleAssets>,
+    mut pool: ResMut<ParticlePool>,
+    camera_query: Query<&Transform, (With<MainCamera>, Without<Particle>)>,
+    mut particle_query: Query<(Entity, &mut Particle, &mut Transform, &mut Visibility, &mut MeshMaterial3d<StandardMaterial>)>,
+) {
+    let delta = time.delta_secs();
+    let camera_position = camera_query.get_single().ok().map(|transform| transform.translation);
+    for (entity, mut particle, mut transform, mut visibility, mut material) in particle_query.iter_mut() {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+            pool.live -= 1;
+            continue;
+        }
+
+        let progress = particle.age / particle.lifetime;
+        let drag = particle.drag.powf(delta);
+        particle.velocity *= drag;
+        transform.translation += particle.velocity * delta;
+        transform.scale = Vec3::splat(particle.size.0.lerp(particle.size.1, progress));
+        if let Some(camera_position) = camera_position {
+            transform.look_at(camera_position, Vec3::Y);
+        }
+
+        let (step, handle) = particle_assets.material(particle.style, progress);
+        if step != particle.step {
+            particle.step = step;
+            material.0 = handle;
+        }
+    }
+}
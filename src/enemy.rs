@@ -3,105 +3,108 @@ use bevy::app::App;
 use bevy::asset::Assets;
 use bevy::math::Vec3;
 use bevy::prelude::*;
-use bevy_rapier3d::dynamics::{Damping, ExternalForce, GravityScale, RigidBody, Velocity};
+use bevy_rapier3d::dynamics::{Damping, ExternalForce, GravityScale, RigidBody};
 use bevy_rapier3d::geometry::Collider;
-use rand::random_range;
 use crate::asset::SpaceKit;
-use crate::bullet::{spawn_bullet, Bullet};
+use crate::bullet::{Bullet, BulletAssets, BulletPool, BulletSet};
+use crate::faction::Faction;
+use crate::game::{AppState, InGame};
+use crate::health::Health;
 use crate::mech::{Mech, RandomFlight};
+use crate::missile::MissileLauncher;
+use crate::rng::{GameRng, RngStream};
 use crate::spaceship::SpaceShip;
+use crate::weapon::{fire_weapon, WeaponDefinitions, WeaponSlot};
+
+const ENEMY_HEALTH: f32 = 50.0;
+const ENEMY_FIRE_RANGE: f32 = 500.0;
 
 #[derive(Component, Debug)]
 pub struct Enemy;
 
-#[derive(Resource, Debug)]
-pub struct EnemyFireRate(Timer);
-
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(EnemyFireRate(Timer::new(Duration::from_secs(1), TimerMode::Repeating)))
-            .add_systems(PostStartup, spawn_enemy)
-            .add_systems(Update, attack);
+        app.add_systems(Update, attack.in_set(BulletSet::Fire).run_if(in_state(AppState::Playing)));
     }
 }
 
-fn spawn_enemy(
-    mut commands: Commands,
-    space_kit: Res<SpaceKit>,
-    mut meshes: ResMut<Assets<Mesh>>,
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    weapons: &WeaponDefinitions,
+    transform: Transform,
 ) {
     let scene_root = SceneRoot(space_kit.enemy.clone());
-    for i in 1..200 {
-        let x = random_range(-1000..1000) as f32;
-        let y = random_range(-1000..1000) as f32;
-        let z = random_range(-1000..1000) as f32;
-        commands.spawn((
-            scene_root.clone(),
-            Transform::from_xyz(x, y, z),
-            ExternalForce::default(),
-            Damping {
-                linear_damping: 0.5,
-                angular_damping: 1.0,
-            },
-            RigidBody::Dynamic,
-            Collider::ball(2.),
-            GravityScale(0.),
-            Mesh3d(meshes.add(Capsule3d::default())),
-            Enemy,
-            RandomFlight {
-                direction: Vec3::ZERO,
-                timer: Timer::new(Duration::from_secs(2), TimerMode::Repeating)
-            },
-        ));
-    }
+    commands.spawn((
+        scene_root,
+        transform,
+        ExternalForce::default(),
+        Damping {
+            linear_damping: 0.5,
+            angular_damping: 1.0,
+        },
+        RigidBody::Dynamic,
+        Collider::ball(2.),
+        GravityScale(0.),
+        Mesh3d(meshes.add(Capsule3d::default())),
+        Enemy,
+        Faction::Enemy,
+        Health::new(ENEMY_HEALTH),
+        WeaponSlot::new(weapons.enemy_loadout.clone()),
+        MissileLauncher::enemy(),
+        StateScoped(InGame),
+        RandomFlight {
+            direction: Vec3::ZERO,
+            timer: Timer::new(Duration::from_secs(2), TimerMode::Repeating)
+        },
+    ));
 }
 
 fn attack(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut pool: ResMut<BulletPool>,
+    bullet_assets: Res<BulletAssets>,
     time: Res<Time>,
-    mut enemy_fire_rate: ResMut<EnemyFireRate>,
+    weapons: Res<WeaponDefinitions>,
+    mut rng: ResMut<GameRng>,
     spaceship_query: Query<&Transform, With<SpaceShip>>,
-    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+    mut enemy_query: Query<(Entity, &Transform, &mut WeaponSlot), With<Enemy>>,
 ) {
-    if enemy_fire_rate.0.tick(time.delta()).just_finished() {
-        if let Ok(spaceship_transform) = spaceship_query.get_single() {
-            for (enemy_entity, enemy_transform) in enemy_query.iter() {
-                if spaceship_transform.translation.distance(enemy_transform.translation) < 500.0 {
-                    // Update enemy rotation to face spaceship
-                    let new_rotation = enemy_transform
-                        .looking_at(spaceship_transform.translation, Vec3::Y)
-                        .rotation;
-
-                    commands.entity(enemy_entity).insert(Transform {
-                        rotation: new_rotation,
-                        ..*enemy_transform
-                    });
-
-                    let spawn_position = enemy_transform.translation
-                        + (spaceship_transform.translation - enemy_transform.translation)
-                        .normalize()
-                        * 15.0;
+    let Ok(spaceship_transform) = spaceship_query.get_single() else {
+        return;
+    };
+    for (enemy_entity, enemy_transform, mut slot) in enemy_query.iter_mut() {
+        let Some((weapon, visual)) = slot.active_weapon().and_then(|name| weapons.get(name).zip(bullet_assets.get(name))) else {
+            continue;
+        };
+        let in_range = spaceship_transform.translation.distance(enemy_transform.translation) < ENEMY_FIRE_RANGE;
+        if slot.fire(time.delta_secs(), in_range, weapon) {
+            // Update enemy rotation to face spaceship
+            let new_rotation = enemy_transform
+                .looking_at(spaceship_transform.translation, Vec3::Y)
+                .rotation;
 
-                    spawn_bullet(
-                        &mut commands,
-                        &mut meshes,
-                        &mut materials,
-                        Velocity {
-                            linvel: (spaceship_transform.translation - enemy_transform.translation)
-                                .normalize()
-                                * 100.0,
-                            ..default()
-                        },
-                        Transform::from_translation(spawn_position),
-                    );
+            commands.entity(enemy_entity).insert(Transform {
+                rotation: new_rotation,
+                ..*enemy_transform
+            });
 
-                    println!("Enemy fired!");
-                }
-            }
+            let direction = (spaceship_transform.translation - enemy_transform.translation).normalize();
+            fire_weapon(
+                &mut commands,
+                &mut pool,
+                visual,
+                rng.stream(RngStream::EnemyWeapons),
+                weapon,
+                enemy_entity,
+                Faction::Enemy,
+                enemy_transform.translation,
+                Quat::from_rotation_arc(Vec3::Z, direction),
+                None,
+            );
         }
-    };
-}
\ No newline at end of file
+    }
+}
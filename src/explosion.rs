@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use crate::enemy::Enemy;
+use crate::game::AppState;
+use crate::health::EntityDestroyed;
+use crate::mech::Mech;
+use crate::rock::Rock;
+use crate::spaceship::SpaceShip;
+
+/// How far, in multiples of its size, an explosion pushes things.
+const BLAST_RANGE: f32 = 15.0;
+/// Impulse per unit of explosion size given to a body right at the centre of the blast.
+const BLAST_IMPULSE: f32 = 150.0;
+
+/// Something blew up. `size` is the rough radius of what exploded.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Explosion {
+    pub position: Vec3,
+    pub size: f32,
+}
+
+pub struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>()
+            .add_systems(Update, (explode_destroyed, push_nearby_bodies).chain().run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn explode_destroyed(mut destroyed_events: EventReader<EntityDestroyed>, mut explosion_events: EventWriter<Explosion>) {
+    for destroyed in destroyed_events.read() {
+        explosion_events.send(Explosion {
+            position: destroyed.position,
+            size: destroyed.size,
+        });
+    }
+}
+
+/// Pushes rocks and ships away from explosions, weaker the further away they are. Pushes
+/// from every blast this tick add up, as do impulses already queued on a body.
+fn push_nearby_bodies(
+    mut commands: Commands,
+    mut explosion_events: EventReader<Explosion>,
+    mut body_query: Query<
+        (Entity, &Transform, Option<&mut ExternalImpulse>),
+        (With<RigidBody>, Or<(With<Rock>, With<SpaceShip>, With<Enemy>, With<Mech>)>),
+    >,
+) {
+    let mut impulses: HashMap<Entity, Vec3> = HashMap::new();
+    for explosion in explosion_events.read() {
+        let range = explosion.size * BLAST_RANGE;
+        for (entity, transform, _) in body_query.iter() {
+            let offset = transform.translation - explosion.position;
+            let distance = offset.length();
+            // The exploding entity itself may still be around this tick
+            if distance >= range || distance < 0.01 {
+                continue;
+            }
+            let falloff = 1.0 - distance / range;
+            *impulses.entry(entity).or_default() += offset / distance * BLAST_IMPULSE * explosion.size * falloff;
+        }
+    }
+    for (entity, impulse) in impulses {
+        let Ok((_, _, external)) = body_query.get_mut(entity) else {
+            continue;
+        };
+        match external {
+            Some(mut external) => external.impulse += impulse,
+            // Other victims of the same blast may be despawned before this is applied
+            None => {
+                commands.entity(entity).try_insert(ExternalImpulse { impulse, ..default() });
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+
+/// Side an entity fights for. Bullets inherit the faction of whoever fired them.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+/// Which factions are hostile to each other and whether allies can hurt each other.
+#[derive(Resource, Debug)]
+pub struct FactionRelations {
+    hostile: HashSet<(Faction, Faction)>,
+    pub friendly_fire: bool,
+}
+
+impl Default for FactionRelations {
+    fn default() -> Self {
+        let mut relations = FactionRelations {
+            hostile: HashSet::new(),
+            friendly_fire: false,
+        };
+        relations.set_hostile(Faction::Player, Faction::Enemy);
+        relations
+    }
+}
+
+impl FactionRelations {
+    pub fn set_hostile(&mut self, a: Faction, b: Faction) {
+        self.hostile.insert((a, b));
+        self.hostile.insert((b, a));
+    }
+
+    pub fn is_hostile(&self, a: Faction, b: Faction) -> bool {
+        self.hostile.contains(&(a, b))
+    }
+
+    pub fn can_damage(&self, attacker: Faction, target: Faction) -> bool {
+        self.is_hostile(attacker, target) || self.friendly_fire
+    }
+
+    /// Whether `attacker` may hurt a target with the given faction. Targets without a
+    /// faction, such as rocks, are neutral and can be hurt by anyone.
+    pub fn allows(&self, attacker: Faction, target: Option<&Faction>) -> bool {
+        target.is_none_or(|target| self.can_damage(attacker, *target))
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FactionRelations>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hostile_factions_damage_each_other() {
+        let relations = FactionRelations::default();
+        assert!(relations.can_damage(Faction::Player, Faction::Enemy));
+        assert!(relations.can_damage(Faction::Enemy, Faction::Player));
+    }
+
+    #[test]
+    fn allies_only_damage_each_other_with_friendly_fire() {
+        let mut relations = FactionRelations::default();
+        assert!(!relations.can_damage(Faction::Player, Faction::Player));
+        assert!(!relations.can_damage(Faction::Enemy, Faction::Enemy));
+        relations.friendly_fire = true;
+        assert!(relations.can_damage(Faction::Player, Faction::Player));
+        assert!(relations.can_damage(Faction::Enemy, Faction::Enemy));
+    }
+
+    #[test]
+    fn anyone_may_hurt_neutral_targets() {
+        let relations = FactionRelations::default();
+        assert!(relations.allows(Faction::Player, None));
+        assert!(relations.allows(Faction::Enemy, None));
+        assert!(relations.allows(Faction::Player, Some(&Faction::Enemy)));
+        assert!(!relations.allows(Faction::Player, Some(&Faction::Player)));
+    }
+}
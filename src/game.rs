@@ -1,40 +1,198 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use crate::bullet::BulletHit;
+use crate::health::{EntityDestroyed, Health, PlayerDestroyed, Shield, VictimKind};
+use crate::laser::LaserEnergy;
+use crate::spaceship::{AimMode, SpaceShip};
+use crate::weapon::WeaponSlot;
 
 #[derive(Resource)]
 pub struct GameState {
     pub score: u32,
 }
 
+/// Top-level phases of the game. Gameplay systems only run while `Playing`.
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Active while a round exists, whether it is running, paused or over.
+/// World entities are spawned on entering it and scoped to it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = AppState;
+
+    fn compute(sources: AppState) -> Option<Self> {
+        match sources {
+            AppState::Playing | AppState::Paused | AppState::GameOver => Some(InGame),
+            AppState::Menu => None,
+        }
+    }
+}
+
+/// Puts a resource back to its default. Entity pools run it when the round ends, since the
+/// entities they keep are scoped to the round and despawned with it.
+pub fn reset_resource<R: Resource + Default>(mut resource: ResMut<R>) {
+    *resource = R::default();
+}
+
 #[derive(Component)]
 pub struct ScoreText;
 
+#[derive(Component)]
+pub struct HealthText;
+
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_ui)
+        app.init_state::<AppState>()
+            .add_computed_state::<InGame>()
+            .enable_state_scoped_entities::<AppState>()
+            .enable_state_scoped_entities::<InGame>()
             .insert_resource(GameState { score: 0 })
-            .add_systems(Update, update_score);
+            .add_systems(OnEnter(AppState::Menu), spawn_state_text("SPACE SHOOTER\nPress Enter to start", AppState::Menu))
+            .add_systems(OnEnter(AppState::Paused), spawn_state_text("PAUSED\nPress Esc to resume", AppState::Paused))
+            .add_systems(OnEnter(AppState::GameOver), spawn_state_text("GAME OVER\nPress Enter to return to menu", AppState::GameOver))
+            .add_systems(OnEnter(InGame), (reset_score, spawn_hud))
+            .add_systems(OnEnter(AppState::Playing), resume_physics)
+            .add_systems(OnExit(AppState::Playing), pause_physics)
+            .add_systems(Update, change_state)
+            .add_systems(Update, (update_score, update_health_text, end_round).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn change_state(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    match state.get() {
+        AppState::Menu if keyboard.just_pressed(KeyCode::Enter) => next_state.set(AppState::Playing),
+        AppState::Playing if keyboard.just_pressed(KeyCode::Escape) => next_state.set(AppState::Paused),
+        AppState::Paused if keyboard.just_pressed(KeyCode::Escape) => next_state.set(AppState::Playing),
+        AppState::GameOver if keyboard.just_pressed(KeyCode::Enter) => next_state.set(AppState::Menu),
+        _ => {}
+    }
+}
+
+fn spawn_state_text(message: &'static str, state: AppState) -> impl Fn(Commands) {
+    move |mut commands: Commands| {
+        commands.spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            StateScoped(state),
+        )).with_children(|parent| {
+            parent.spawn((
+                Text(message.to_string()),
+                TextLayout::new_with_justify(JustifyText::Center),
+            ));
+        });
+    }
+}
+
+fn end_round(
+    mut player_destroyed_events: EventReader<PlayerDestroyed>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if player_destroyed_events.read().next().is_some() {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+fn reset_score(mut game_state: ResMut<GameState>) {
+    game_state.score = 0;
+}
+
+fn resume_physics(mut rapier_config: Query<&mut RapierConfiguration>) {
+    for mut config in rapier_config.iter_mut() {
+        config.physics_pipeline_active = true;
+    }
+}
+
+fn pause_physics(mut rapier_config: Query<&mut RapierConfiguration>) {
+    for mut config in rapier_config.iter_mut() {
+        config.physics_pipeline_active = false;
+    }
+}
+
+fn kill_reward(kind: VictimKind) -> u32 {
+    match kind {
+        VictimKind::Enemy => 25,
+        VictimKind::Mech => 10,
+        VictimKind::Rock => 2,
+        VictimKind::Turret => 30,
+        VictimKind::SpaceShip => 0,
     }
 }
 
 fn update_score(mut game_state: ResMut<GameState>,
                 mut bullet_hit_events: EventReader<BulletHit>,
+                mut destroyed_events: EventReader<EntityDestroyed>,
+                player_query: Query<(), With<SpaceShip>>,
                 mut score_text: Query<&mut Text, With<ScoreText>>) {
     bullet_hit_events.read().for_each(&mut |bullet_hit: &BulletHit| {
-        game_state.score += bullet_hit.count;
+        if bullet_hit.damage > 0.0 && player_query.contains(bullet_hit.shooter) {
+            game_state.score += 1;
+        }
     });
+    for destroyed in destroyed_events.read() {
+        if destroyed.killer.is_some_and(|killer| player_query.contains(killer)) {
+            game_state.score += kill_reward(destroyed.kind);
+        }
+    }
     if let Ok(mut text) = score_text.get_single_mut() {
         text.0 = format!("Score: {}", game_state.score);
     }
 }
 
-fn setup_ui(mut commands: Commands) {
+fn update_health_text(
+    aim_mode: Res<AimMode>,
+    ship_query: Query<(&Health, &Shield, &LaserEnergy, &WeaponSlot), With<SpaceShip>>,
+    mut health_text: Query<&mut Text, With<HealthText>>,
+) {
+    if let (Ok((health, shield, energy, slot)), Ok(mut text)) = (ship_query.get_single(), health_text.get_single_mut()) {
+        text.0 = format!(
+            "Hull: {:.0}/{:.0}  Shield: {:.0}/{:.0}  Laser: {:.0}/{:.0}  Weapon: {}  Aim: {}",
+            health.current, health.max, shield.current, shield.max, energy.current, energy.max,
+            slot.active_weapon().unwrap_or("None"),
+            match *aim_mode {
+                AimMode::Forward => "Forward",
+                AimMode::Mouse => "Mouse",
+            }
+        );
+    }
+}
+
+/// Score and ship status, shown for as long as the round exists.
+fn spawn_hud(mut commands: Commands) {
     commands.spawn((
         Text("Score: 0".to_string()),
         TextLayout::new_with_justify(JustifyText::Left),
         ScoreText,
+        StateScoped(InGame),
+    ));
+    commands.spawn((
+        Text::default(),
+        TextLayout::new_with_justify(JustifyText::Left),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(24.0),
+            ..default()
+        },
+        HealthText,
+        StateScoped(InGame),
     ));
 }
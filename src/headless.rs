@@ -0,0 +1,240 @@
+use std::time::{Duration, Instant};
+use bevy::app::{App, PluginsState};
+use bevy::asset::AssetPlugin;
+use bevy::input::InputPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+use crate::args::Args;
+use crate::asset::SpaceKit;
+use crate::bot::BotPlugin;
+use crate::bullet::Bullet;
+use crate::enemy::Enemy;
+use crate::game::{AppState, GameState, InGame};
+use crate::health::{EntityDestroyed, PlayerDestroyed};
+use crate::mech::Mech;
+use crate::replay::{ReplayMode, TICK_RATE};
+use crate::rng::GameRng;
+use crate::rock::Rock;
+use crate::sector::Sectors;
+use crate::spaceship::SpaceShip;
+
+const DEFAULT_TICKS: u32 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pilot {
+    Bot,
+    Idle,
+    Replay,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessOptions {
+    pub ticks: u32,
+    pub pilot: Pilot,
+}
+
+impl HeadlessOptions {
+    /// Returns options when `--headless` was passed, reading `--ticks <n>` and `--pilot bot|idle`.
+    /// A `--replay` recording always takes over the controls.
+    pub fn from_args(args: &Args, replay_mode: &ReplayMode) -> Option<Self> {
+        if !args.has("--headless") {
+            return None;
+        }
+        let ticks = args.value_of("--ticks")
+            .and_then(|ticks| ticks.parse().ok())
+            .unwrap_or(DEFAULT_TICKS);
+        let pilot = match (replay_mode, args.value_of("--pilot")) {
+            (ReplayMode::Replay(_), _) => Pilot::Replay,
+            (_, Some("idle")) => Pilot::Idle,
+            _ => Pilot::Bot,
+        };
+        Some(HeadlessOptions { ticks, pilot })
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+struct RunStats {
+    rounds: u32,
+    banked_score: u32,
+    kills: u32,
+    deaths: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityCounts {
+    pub total: u32,
+    pub enemies: usize,
+    pub mechs: usize,
+    pub rocks: usize,
+    pub bullets: usize,
+    pub sectors: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub seed: u64,
+    pub ticks: u32,
+    pub pilot: Pilot,
+    pub rounds: u32,
+    pub score: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub entities: EntityCounts,
+    pub total_seconds: f64,
+    pub mean_tick_ms: f64,
+    pub max_tick_ms: f64,
+}
+
+/// Runs the gameplay plugins without a window or renderer for a number of ticks and
+/// prints a JSON report of the run. The report is all that goes to stdout, log messages go
+/// to stderr, so the output can be piped straight into other tools.
+pub fn run(options: HeadlessOptions, seed: Option<u64>, replay_mode: ReplayMode) {
+    let (_, report) = simulate(options, seed, replay_mode);
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
+
+/// Runs the simulation like `run` does, handing back the app as the run left it along
+/// with the report.
+pub fn simulate(options: HeadlessOptions, seed: Option<u64>, replay_mode: ReplayMode) -> (App, Report) {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins((
+            LogPlugin::default(),
+            TransformPlugin,
+            HierarchyPlugin,
+            InputPlugin,
+            StatesPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .insert_resource(placeholder_space_kit())
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
+    crate::add_gameplay_plugins(&mut app, seed, replay_mode);
+
+    let tick = Duration::from_secs_f64(1.0 / TICK_RATE);
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+        .insert_resource(TimestepMode::Fixed {
+            dt: tick.as_secs_f32(),
+            substeps: 1,
+        })
+        .init_resource::<RunStats>()
+        .add_systems(OnEnter(InGame), count_round)
+        .add_systems(OnExit(InGame), bank_score)
+        .add_systems(Update, count_kills_and_deaths);
+    match options.pilot {
+        Pilot::Bot => {
+            app.add_plugins(BotPlugin);
+        }
+        Pilot::Idle | Pilot::Replay => {}
+    }
+    if options.pilot != Pilot::Replay {
+        app.add_systems(Update, restart_rounds);
+    }
+
+    while app.plugins_state() == PluginsState::Adding {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+    app.world_mut().resource_mut::<NextState<AppState>>().set(AppState::Playing);
+
+    let started = Instant::now();
+    let mut max_tick = Duration::ZERO;
+    let mut ticks = 0;
+    while ticks < options.ticks {
+        let tick_started = Instant::now();
+        app.update();
+        max_tick = max_tick.max(tick_started.elapsed());
+        ticks += 1;
+
+        let state = *app.world().resource::<State<AppState>>().get();
+        if options.pilot == Pilot::Replay && state == AppState::GameOver {
+            break;
+        }
+    }
+    let total = started.elapsed();
+
+    let report = build_report(app.world_mut(), options.pilot, ticks, total, max_tick);
+    (app, report)
+}
+
+/// The headless runner never renders, so scenes are replaced by empty handles.
+fn placeholder_space_kit() -> SpaceKit {
+    SpaceKit {
+        planets: vec![Handle::default(); 11],
+        mechs: vec![Handle::default(); 4],
+        ..default()
+    }
+}
+
+fn count_round(mut stats: ResMut<RunStats>) {
+    stats.rounds += 1;
+}
+
+fn bank_score(mut stats: ResMut<RunStats>, game_state: Res<GameState>) {
+    stats.banked_score += game_state.score;
+}
+
+fn count_kills_and_deaths(
+    mut stats: ResMut<RunStats>,
+    mut destroyed_events: EventReader<EntityDestroyed>,
+    mut player_destroyed_events: EventReader<PlayerDestroyed>,
+    player_query: Query<(), With<SpaceShip>>,
+) {
+    for destroyed in destroyed_events.read() {
+        if destroyed.killer.is_some_and(|killer| player_query.contains(killer)) {
+            stats.kills += 1;
+        }
+    }
+    stats.deaths += player_destroyed_events.read().count() as u32;
+}
+
+/// Starts a fresh round whenever the previous one ended, so long runs keep playing.
+fn restart_rounds(state: Res<State<AppState>>, mut next_state: ResMut<NextState<AppState>>) {
+    match state.get() {
+        AppState::GameOver => next_state.set(AppState::Menu),
+        AppState::Menu => next_state.set(AppState::Playing),
+        AppState::Playing | AppState::Paused => {}
+    }
+}
+
+fn count<F: bevy::ecs::query::QueryFilter>(world: &mut World) -> usize {
+    world.query_filtered::<(), F>().iter(world).count()
+}
+
+fn build_report(world: &mut World, pilot: Pilot, ticks: u32, total: Duration, max_tick: Duration) -> Report {
+    let in_round = world.resource::<State<AppState>>().get() != &AppState::Menu;
+    let current_score = if in_round { world.resource::<GameState>().score } else { 0 };
+    let stats = world.resource::<RunStats>();
+    let (rounds, score, kills, deaths) = (stats.rounds, stats.banked_score + current_score, stats.kills, stats.deaths);
+
+    Report {
+        seed: world.resource::<GameRng>().seed(),
+        ticks,
+        pilot,
+        rounds,
+        score,
+        kills,
+        deaths,
+        entities: EntityCounts {
+            total: world.entities().len(),
+            enemies: count::<With<Enemy>>(world),
+            mechs: count::<With<Mech>>(world),
+            rocks: count::<With<Rock>>(world),
+            // Pooled bullets waiting to be fired again don't count
+            bullets: count::<(With<Bullet>, Without<ColliderDisabled>)>(world),
+            sectors: world.resource::<Sectors>().loaded(),
+        },
+        total_seconds: total.as_secs_f64(),
+        mean_tick_ms: total.as_secs_f64() * 1000.0 / ticks.max(1) as f64,
+        max_tick_ms: max_tick.as_secs_f64() * 1000.0,
+    }
+}
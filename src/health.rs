@@ -0,0 +1,160 @@
+use std::time::Duration;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use crate::enemy::Enemy;
+use crate::game::AppState;
+use crate::mech::Mech;
+use crate::rock::Rock;
+use crate::spaceship::SpaceShip;
+use crate::station::Turret;
+
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health { current: max, max }
+    }
+}
+
+/// Absorbs damage before the hull and recharges once no damage was taken for `regen_delay`.
+#[derive(Component, Debug)]
+pub struct Shield {
+    pub current: f32,
+    pub max: f32,
+    pub regen_rate: f32,
+    pub regen_delay: Timer,
+}
+
+impl Shield {
+    pub fn new(max: f32, regen_rate: f32, regen_delay: Duration) -> Self {
+        Shield {
+            current: max,
+            max,
+            regen_rate,
+            regen_delay: Timer::new(regen_delay, TimerMode::Once),
+        }
+    }
+}
+
+/// Marks the player's ship once its hull is gone. The ship is hidden rather than despawned,
+/// so anything that acts on it should skip it while this is set.
+#[derive(Component, Debug)]
+pub struct Destroyed;
+
+#[derive(Event, Debug)]
+pub struct DamageDealt {
+    pub target: Entity,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+#[derive(Event, Debug)]
+pub struct PlayerDestroyed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VictimKind {
+    SpaceShip,
+    Enemy,
+    Mech,
+    Rock,
+    Turret,
+}
+
+impl VictimKind {
+    /// Collider radius of an unscaled victim of this kind.
+    fn radius(&self) -> f32 {
+        match self {
+            VictimKind::SpaceShip | VictimKind::Enemy | VictimKind::Mech => 2.0,
+            VictimKind::Turret => 1.0,
+            VictimKind::Rock => 1.0,
+        }
+    }
+}
+
+/// Sent when any entity's health drops to zero. `killer` is whoever dealt the final blow.
+#[derive(Event, Debug)]
+pub struct EntityDestroyed {
+    pub kind: VictimKind,
+    pub position: Vec3,
+    /// Rough radius of what was destroyed.
+    pub size: f32,
+    pub killer: Option<Entity>,
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageDealt>()
+            .add_event::<PlayerDestroyed>()
+            .add_event::<EntityDestroyed>()
+            .add_systems(Update, (apply_damage, regenerate_shields).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageDealt>,
+    mut player_destroyed_events: EventWriter<PlayerDestroyed>,
+    mut destroyed_events: EventWriter<EntityDestroyed>,
+    mut target_query: Query<(&mut Health, Option<&mut Shield>, &Transform, Has<SpaceShip>, Has<Enemy>, Has<Mech>, Has<Rock>, Has<Turret>)>,
+) {
+    for damage in damage_events.read() {
+        let Ok((mut health, shield, transform, is_player, is_enemy, is_mech, is_rock, is_turret)) = target_query.get_mut(damage.target) else {
+            continue;
+        };
+        if health.current <= 0.0 {
+            continue;
+        }
+        let mut remaining = damage.amount;
+        if let Some(mut shield) = shield {
+            let absorbed = remaining.min(shield.current);
+            shield.current -= absorbed;
+            remaining -= absorbed;
+            shield.regen_delay.reset();
+        }
+        health.current = (health.current - remaining).max(0.0);
+        if health.current > 0.0 {
+            continue;
+        }
+
+        let kind = if is_player {
+            VictimKind::SpaceShip
+        } else if is_enemy {
+            VictimKind::Enemy
+        } else if is_mech {
+            VictimKind::Mech
+        } else if is_rock {
+            VictimKind::Rock
+        } else if is_turret {
+            VictimKind::Turret
+        } else {
+            continue;
+        };
+        destroyed_events.send(EntityDestroyed {
+            kind,
+            position: transform.translation,
+            size: kind.radius() * transform.scale.max_element(),
+            killer: damage.source,
+        });
+        if is_player {
+            // The ship stays around so the camera keeps something to follow on the game over screen
+            commands.entity(damage.target).insert((Visibility::Hidden, Destroyed));
+            player_destroyed_events.send(PlayerDestroyed);
+        } else {
+            commands.entity(damage.target).despawn_recursive();
+        }
+    }
+}
+
+fn regenerate_shields(time: Res<Time>, mut shield_query: Query<&mut Shield>) {
+    for mut shield in shield_query.iter_mut() {
+        if shield.regen_delay.tick(time.delta()).finished() {
+            shield.current = (shield.current + shield.regen_rate * time.delta_secs()).min(shield.max);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use crate::bullet::{contact_point, Bullet};
+use crate::game::AppState;
+use crate::health::{DamageDealt, Health};
+
+/// Contact force, in newtons, above which bodies report contacts at all. Scrapes start here.
+const SCRAPE_FORCE_THRESHOLD: f32 = 500.0;
+/// Impulse a body shrugs off without damage, so nudges and resting contact are free.
+const MIN_DAMAGING_IMPULSE: f32 = 50.0;
+const DAMAGE_PER_IMPULSE: f32 = 0.05;
+/// Share of the damage taken by the body that did the ramming, when it rammed something
+/// that can be damaged too.
+const RAMMER_DAMAGE_SHARE: f32 = 0.5;
+/// After taking impact damage a body can't take more for this long, so bodies grinding
+/// against each other aren't damaged every physics step.
+const INVULNERABILITY: Duration = Duration::from_millis(500);
+
+/// Two bodies hit or scraped each other. `damage` is zero for a scrape that did no harm.
+#[derive(Event, Debug)]
+pub struct Impact {
+    pub point: Vec3,
+    /// Direction the contact pushed the first body in.
+    pub normal: Vec3,
+    pub damage: f32,
+}
+
+/// Time left until a body can take impact damage again.
+#[derive(Component, Debug)]
+struct ImpactCooldown(Timer);
+
+pub struct ImpactPlugin;
+
+impl Plugin for ImpactPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Impact>()
+            .add_systems(Update, (enable_contact_forces, tick_cooldowns, damage_on_impact).chain().run_if(in_state(AppState::Playing)));
+    }
+}
+
+/// Makes everything that can be damaged report contact forces and keep its velocity up to date.
+fn enable_contact_forces(
+    mut commands: Commands,
+    body_query: Query<(Entity, Option<&ActiveEvents>, Has<Velocity>), (Added<Health>, With<RigidBody>)>,
+) {
+    for (entity, events, has_velocity) in body_query.iter() {
+        let events = events.copied().unwrap_or_default() | ActiveEvents::CONTACT_FORCE_EVENTS;
+        let mut cooldown = Timer::new(INVULNERABILITY, TimerMode::Once);
+        cooldown.tick(INVULNERABILITY);
+        commands.entity(entity).insert((events, ContactForceEventThreshold(SCRAPE_FORCE_THRESHOLD), ImpactCooldown(cooldown)));
+        if !has_velocity {
+            commands.entity(entity).insert(Velocity::default());
+        }
+    }
+}
+
+fn tick_cooldowns(time: Res<Time>, mut cooldown_query: Query<&mut ImpactCooldown>) {
+    for mut cooldown in cooldown_query.iter_mut() {
+        cooldown.0.tick(time.delta());
+    }
+}
+
+/// Length of the physics step contact forces are measured over. Rapier reports each force
+/// as the impulse of one substep divided by the substep's length.
+fn physics_step(timestep: &TimestepMode, time: &Time) -> f32 {
+    match *timestep {
+        TimestepMode::Fixed { dt, substeps } | TimestepMode::Interpolated { dt, substeps, .. } => dt / substeps as f32,
+        TimestepMode::Variable { max_dt, time_scale, substeps } => {
+            (time.delta_secs() * time_scale).min(max_dt) / substeps as f32
+        }
+    }
+}
+
+/// Damages both bodies of a hard enough contact by the impulse the contact took. That
+/// impulse is the momentum the hit changed, so it already grows with both the speed the
+/// bodies closed in at and their masses: a heavy rock hurts more than a light one at the
+/// same speed.
+fn damage_on_impact(
+    time: Res<Time>,
+    timestep: Res<TimestepMode>,
+    rapier_context: ReadRapierContext,
+    mut contact_force_events: EventReader<ContactForceEvent>,
+    mut damage_events: EventWriter<DamageDealt>,
+    mut impact_events: EventWriter<Impact>,
+    mut body_query: Query<(&Transform, Option<&Velocity>, Option<&mut ImpactCooldown>), (With<RigidBody>, Without<Bullet>)>,
+) {
+    let rapier_context = rapier_context.single();
+    for event in contact_force_events.read() {
+        let (e1, e2) = (event.collider1, event.collider2);
+        let Ok([(transform1, velocity1, _), (transform2, velocity2, _)]) = body_query.get_many([e1, e2]) else {
+            continue;
+        };
+        let (position1, position2) = (transform1.translation, transform2.translation);
+        let (point, normal) = contact_point(&rapier_context, e1, e2)
+            .unwrap_or(((position1 + position2) / 2.0, Vec3::ZERO));
+        // Make the normal point from the second body towards the first
+        let apart = (position1 - position2).normalize_or_zero();
+        let normal = if normal == Vec3::ZERO || normal.dot(apart) < 0.0 { apart } else { normal };
+
+        let impulse = event.total_force_magnitude * physics_step(&timestep, &time);
+        let damage = (impulse - MIN_DAMAGING_IMPULSE).max(0.0) * DAMAGE_PER_IMPULSE;
+        if damage <= 0.0 {
+            impact_events.send(Impact { point, normal, damage: 0.0 });
+            continue;
+        }
+
+        // Whoever was flying harder into the other did the ramming
+        let closing1 = velocity1.map_or(0.0, |velocity| velocity.linvel.dot(-normal));
+        let closing2 = velocity2.map_or(0.0, |velocity| velocity.linvel.dot(normal));
+        let both_damageable = body_query.get(e1).is_ok_and(|(_, _, cooldown)| cooldown.is_some())
+            && body_query.get(e2).is_ok_and(|(_, _, cooldown)| cooldown.is_some());
+        let share = |rammer: bool| if rammer && both_damageable { RAMMER_DAMAGE_SHARE } else { 1.0 };
+
+        let mut dealt = 0.0f32;
+        for (target, source, rammer) in [(e1, e2, closing1 > closing2), (e2, e1, closing2 >= closing1)] {
+            let Ok((_, _, Some(mut cooldown))) = body_query.get_mut(target) else {
+                continue;
+            };
+            if !cooldown.0.finished() {
+                continue;
+            }
+            cooldown.0.reset();
+            let amount = damage * share(rammer);
+            dealt = dealt.max(amount);
+            damage_events.send(DamageDealt {
+                target,
+                amount,
+                source: Some(source),
+            });
+        }
+        impact_events.send(Impact { point, normal, damage: dealt });
+    }
+}
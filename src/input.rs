@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::path::Path;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::asset::load_ron;
+use crate::game::AppState;
+
+const BINDINGS_PATH: &str = "assets/input_bindings.ron";
+
+/// What the pilot wants the ship to do this tick. Axes range from -1 to 1.
+/// Ship systems read this instead of raw devices so input can be recorded and replayed.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PilotActions {
+    pub thrust: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub roll: f32,
+    pub fire: bool,
+    /// Held to keep the laser firing.
+    pub laser: bool,
+    /// Held to lock a missile on, released to launch it.
+    pub missile: bool,
+    pub boost: bool,
+    pub next_weapon: bool,
+    pub previous_weapon: bool,
+    /// Picks the next hostile for missiles to lock onto.
+    pub target_next: bool,
+    /// World space ray to aim along, `None` fires straight ahead.
+    pub aim: Option<Ray3d>,
+}
+
+/// Named things the player can do, independent of the device they do it with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Thrust,
+    Pitch,
+    Yaw,
+    Roll,
+    Fire,
+    Laser,
+    Missile,
+    Boost,
+    NextWeapon,
+    PreviousWeapon,
+    TargetNext,
+    ToggleAim,
+}
+
+/// A physical input that drives an action. Buttons report 1 while held.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Two keys forming an axis, e.g. W/S for thrust.
+    KeyAxis { positive: KeyCode, negative: KeyCode },
+    /// A gamepad button. Analog triggers report how far they are pulled.
+    GamepadButton(GamepadButton),
+    /// Two gamepad buttons forming an axis, e.g. the triggers for throttle.
+    GamepadButtonAxis { positive: GamepadButton, negative: GamepadButton },
+    /// An analog stick or joystick axis, shaped by `tuning`.
+    GamepadAxis {
+        axis: GamepadAxis,
+        #[serde(default)]
+        tuning: AxisTuning,
+    },
+}
+
+/// How the raw value of an analog axis is turned into an action value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisTuning {
+    /// Values closer to the centre than this are ignored, so a worn stick doesn't drift.
+    pub dead_zone: f32,
+    /// Exponent of the response curve. 1 is linear, higher values give finer control
+    /// near the centre.
+    pub curve: f32,
+    pub invert: bool,
+    /// Maps the full travel of the axis onto 0..1 instead of -1..1, for throttle levers
+    /// that rest at one end.
+    pub lever: bool,
+}
+
+impl Default for AxisTuning {
+    fn default() -> Self {
+        AxisTuning {
+            dead_zone: 0.1,
+            curve: 1.0,
+            invert: false,
+            lever: false,
+        }
+    }
+}
+
+impl AxisTuning {
+    pub fn apply(&self, raw: f32) -> f32 {
+        let raw = if self.lever { (raw + 1.0) / 2.0 } else { raw };
+        let magnitude = raw.abs();
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+        // Rescale so the value still starts from 0 at the edge of the dead zone
+        let scaled = ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+        let value = scaled.powf(self.curve).copysign(raw);
+        if self.invert { -value } else { value }
+    }
+}
+
+/// Which inputs drive which action, loaded from `assets/input_bindings.ron`.
+/// An action can have any number of bindings; their values are summed.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct InputBindings {
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let axis = |positive, negative| Binding::KeyAxis { positive, negative };
+        let stick = |axis, curve, invert| Binding::GamepadAxis {
+            axis,
+            tuning: AxisTuning { curve, invert, ..default() },
+        };
+        InputBindings {
+            bindings: HashMap::from([
+                (Action::Thrust, vec![
+                    axis(KeyCode::KeyW, KeyCode::KeyS),
+                    Binding::GamepadButtonAxis { positive: GamepadButton::RightTrigger2, negative: GamepadButton::LeftTrigger2 },
+                ]),
+                (Action::Pitch, vec![axis(KeyCode::ArrowUp, KeyCode::ArrowDown), stick(GamepadAxis::LeftStickY, 1.5, false)]),
+                // Positive yaw and roll turn the ship left, sticks report right as positive
+                (Action::Yaw, vec![axis(KeyCode::KeyA, KeyCode::KeyD), stick(GamepadAxis::LeftStickX, 1.5, true)]),
+                (Action::Roll, vec![axis(KeyCode::KeyQ, KeyCode::KeyE), stick(GamepadAxis::RightStickX, 1.0, true)]),
+                (Action::Fire, vec![Binding::Key(KeyCode::Space), Binding::Mouse(MouseButton::Left), Binding::GamepadButton(GamepadButton::South)]),
+                (Action::Laser, vec![Binding::Key(KeyCode::KeyF), Binding::Mouse(MouseButton::Right), Binding::GamepadButton(GamepadButton::RightTrigger)]),
+                (Action::Missile, vec![Binding::Key(KeyCode::KeyR), Binding::Mouse(MouseButton::Middle), Binding::GamepadButton(GamepadButton::East)]),
+                (Action::Boost, vec![Binding::Key(KeyCode::ShiftLeft), Binding::GamepadButton(GamepadButton::LeftThumb)]),
+                (Action::NextWeapon, vec![Binding::Key(KeyCode::KeyX), Binding::GamepadButton(GamepadButton::DPadRight)]),
+                (Action::PreviousWeapon, vec![Binding::Key(KeyCode::KeyZ), Binding::GamepadButton(GamepadButton::DPadLeft)]),
+                (Action::TargetNext, vec![Binding::Key(KeyCode::KeyT), Binding::GamepadButton(GamepadButton::North)]),
+                (Action::ToggleAim, vec![Binding::Key(KeyCode::KeyV)]),
+            ]),
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        load_ron(path)
+    }
+}
+
+/// Current value of every bound action, and the value it had on the previous tick.
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    previous: HashMap<Action, f32>,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) != 0.0
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.previous.get(&action).copied().unwrap_or(0.0) == 0.0
+    }
+}
+
+/// Systems that fill `PilotActions` for the current tick. Devices are read first and
+/// anything in `Override` (such as a replay) may then replace what they produced.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputSet {
+    Devices,
+    Override,
+}
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        let bindings = InputBindings::load(Path::new(BINDINGS_PATH)).unwrap_or_else(|error| {
+            warn!("Using default input bindings, could not load {}", error);
+            InputBindings::default()
+        });
+        app.insert_resource(bindings)
+            .init_resource::<ActionState>()
+            .init_resource::<PilotActions>()
+            .configure_sets(Update, InputSet::Devices.before(InputSet::Override))
+            .add_systems(Update, (update_action_state, read_actions).chain().in_set(InputSet::Devices).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn binding_value(
+    binding: &Binding,
+    keyboard: &ButtonInput<KeyCode>,
+    mouse: &ButtonInput<MouseButton>,
+    gamepads: &Query<&Gamepad>,
+) -> f32 {
+    let held = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+    // Every connected gamepad or joystick can fly the ship
+    let button = |button: GamepadButton| -> f32 {
+        gamepads.iter().map(|gamepad| gamepad.get(button).unwrap_or(0.0)).sum()
+    };
+    match binding {
+        Binding::Key(key) => held(keyboard.pressed(*key)),
+        Binding::Mouse(button) => held(mouse.pressed(*button)),
+        Binding::KeyAxis { positive, negative } => held(keyboard.pressed(*positive)) - held(keyboard.pressed(*negative)),
+        Binding::GamepadButton(gamepad_button) => button(*gamepad_button),
+        Binding::GamepadButtonAxis { positive, negative } => button(*positive) - button(*negative),
+        Binding::GamepadAxis { axis, tuning } => gamepads
+            .iter()
+            .filter_map(|gamepad| gamepad.get(*axis))
+            .map(|raw| tuning.apply(raw))
+            .sum(),
+    }
+}
+
+fn update_action_state(
+    bindings: Res<InputBindings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut action_state: ResMut<ActionState>,
+) {
+    action_state.previous = std::mem::take(&mut action_state.values);
+    action_state.values = bindings.bindings
+        .iter()
+        .map(|(action, action_bindings)| {
+            let value: f32 = action_bindings
+                .iter()
+                .map(|binding| binding_value(binding, &keyboard, &mouse, &gamepads))
+                .sum();
+            (*action, value.clamp(-1.0, 1.0))
+        })
+        .collect();
+}
+
+fn read_actions(action_state: Res<ActionState>, mut actions: ResMut<PilotActions>) {
+    *actions = PilotActions {
+        thrust: action_state.value(Action::Thrust),
+        pitch: action_state.value(Action::Pitch),
+        yaw: action_state.value(Action::Yaw),
+        roll: action_state.value(Action::Roll),
+        fire: action_state.pressed(Action::Fire),
+        laser: action_state.pressed(Action::Laser),
+        missile: action_state.pressed(Action::Missile),
+        boost: action_state.pressed(Action::Boost),
+        next_weapon: action_state.just_pressed(Action::NextWeapon),
+        previous_weapon: action_state.just_pressed(Action::PreviousWeapon),
+        target_next: action_state.just_pressed(Action::TargetNext),
+        aim: None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone_swallows_small_values() {
+        let tuning = AxisTuning::default();
+        assert_eq!(tuning.apply(0.05), 0.0);
+        assert_eq!(tuning.apply(-0.1), 0.0);
+    }
+
+    #[test]
+    fn response_starts_at_dead_zone_and_reaches_full_travel() {
+        let tuning = AxisTuning { dead_zone: 0.2, ..default() };
+        assert!(tuning.apply(0.21) < 0.02);
+        assert_eq!(tuning.apply(1.0), 1.0);
+        assert_eq!(tuning.apply(-1.0), -1.0);
+        assert!((tuning.apply(0.6) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn curve_softens_the_centre() {
+        let linear = AxisTuning { dead_zone: 0.0, ..default() };
+        let curved = AxisTuning { dead_zone: 0.0, curve: 2.0, ..default() };
+        assert!((curved.apply(0.5) - 0.25).abs() < 1e-6);
+        assert!((curved.apply(-0.5) + 0.25).abs() < 1e-6);
+        assert!(curved.apply(0.5) < linear.apply(0.5));
+    }
+
+    #[test]
+    fn invert_flips_direction() {
+        let tuning = AxisTuning { invert: true, ..default() };
+        assert_eq!(tuning.apply(1.0), -1.0);
+        assert_eq!(tuning.apply(-1.0), 1.0);
+    }
+
+    #[test]
+    fn lever_maps_full_travel_onto_zero_to_one() {
+        let tuning = AxisTuning { dead_zone: 0.0, lever: true, ..default() };
+        assert_eq!(tuning.apply(-1.0), 0.0);
+        assert!((tuning.apply(0.0) - 0.5).abs() < 1e-6);
+        assert_eq!(tuning.apply(1.0), 1.0);
+    }
+}
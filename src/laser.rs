@@ -0,0 +1,191 @@
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use crate::bullet::solid_ray_filter;
+use crate::faction::{Faction, FactionRelations};
+use crate::game::{AppState, InGame};
+use crate::health::{DamageDealt, Destroyed, Health};
+use crate::input::{InputSet, PilotActions};
+use crate::spaceship::SpaceShip;
+
+const LASER_ENERGY: f32 = 100.0;
+const LASER_DRAIN_RATE: f32 = 25.0;
+const LASER_RECHARGE_RATE: f32 = 15.0;
+/// Energy an emptied laser has to recharge to before it fires again.
+const LASER_REFIRE_ENERGY: f32 = 20.0;
+const LASER_DAMAGE_PER_SECOND: f32 = 40.0;
+const LASER_RANGE: f32 = 1500.0;
+/// Distance from the ship's centre to where the beam leaves the hull.
+const LASER_MUZZLE_OFFSET: f32 = 5.0;
+
+/// Energy pool the laser drains while firing. Recharges whenever the laser is off.
+#[derive(Component, Debug)]
+pub struct LaserEnergy {
+    pub current: f32,
+    pub max: f32,
+    /// Set when the laser ran dry, until it recharged to `LASER_REFIRE_ENERGY`.
+    depleted: bool,
+}
+
+impl Default for LaserEnergy {
+    fn default() -> Self {
+        LaserEnergy {
+            current: LASER_ENERGY,
+            max: LASER_ENERGY,
+            depleted: false,
+        }
+    }
+}
+
+impl LaserEnergy {
+    /// Drains `delta` seconds worth of energy when the trigger is held and the laser can
+    /// fire, recharges otherwise. Returns whether the laser fires. An emptied laser stays
+    /// off until it recharged to `LASER_REFIRE_ENERGY`, so holding the trigger doesn't
+    /// flicker the beam.
+    pub fn fire(&mut self, delta: f32, trigger: bool) -> bool {
+        if self.depleted && self.current >= LASER_REFIRE_ENERGY {
+            self.depleted = false;
+        }
+        if !trigger || self.depleted {
+            self.current = (self.current + LASER_RECHARGE_RATE * delta).min(self.max);
+            return false;
+        }
+        self.current = (self.current - LASER_DRAIN_RATE * delta).max(0.0);
+        self.depleted = self.current == 0.0;
+        true
+    }
+}
+
+#[derive(Component)]
+struct LaserBeam;
+
+#[derive(Component)]
+struct LaserImpact;
+
+pub struct LaserPlugin;
+
+impl Plugin for LaserPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(InGame), spawn_laser_beam)
+            .add_systems(Update, fire_laser.after(InputSet::Override).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn spawn_laser_beam(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgba(0.0, 1.0, 0.0, 0.5),
+        emissive: LinearRgba::from(Color::srgb(0.0, 3.0, 0.0)),
+        unlit: true,
+        alpha_mode: AlphaMode::Add,
+        ..default()
+    });
+
+    // A unit cylinder along Y, stretched to the hit distance every frame
+    commands.spawn((
+        Mesh3d(meshes.add(Cylinder::new(0.25, 1.0))),
+        MeshMaterial3d(material.clone()),
+        Transform::default(),
+        Visibility::Hidden,
+        LaserBeam,
+        StateScoped(InGame),
+    ));
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(1.5).mesh().ico(3).unwrap())),
+        MeshMaterial3d(material),
+        Transform::default(),
+        Visibility::Hidden,
+        LaserImpact,
+        StateScoped(InGame),
+    ));
+}
+
+fn fire_laser(
+    time: Res<Time>,
+    actions: Res<PilotActions>,
+    relations: Res<FactionRelations>,
+    rapier_context: ReadRapierContext,
+    mut damage_events: EventWriter<DamageDealt>,
+    mut ship_query: Query<(Entity, &Transform, &Faction, &mut LaserEnergy, Has<Destroyed>), With<SpaceShip>>,
+    target_query: Query<(Option<&Faction>, Has<Health>)>,
+    mut beam_query: Query<(&mut Transform, &mut Visibility), (With<LaserBeam>, Without<SpaceShip>, Without<LaserImpact>)>,
+    mut impact_query: Query<(&mut Transform, &mut Visibility), (With<LaserImpact>, Without<SpaceShip>, Without<LaserBeam>)>,
+) {
+    let (Ok((mut beam_transform, mut beam_visibility)), Ok((mut impact_transform, mut impact_visibility))) =
+        (beam_query.get_single_mut(), impact_query.get_single_mut()) else {
+        return;
+    };
+    *beam_visibility = Visibility::Hidden;
+    *impact_visibility = Visibility::Hidden;
+
+    let Ok((ship_entity, ship_transform, ship_faction, mut energy, destroyed)) = ship_query.get_single_mut() else {
+        return;
+    };
+    let delta = time.delta_secs();
+    if !energy.fire(delta, actions.laser && !destroyed) {
+        return;
+    }
+
+    // The ship's nose points along -forward
+    let direction = -ship_transform.forward().as_vec3();
+    let origin = ship_transform.translation + direction * LASER_MUZZLE_OFFSET;
+    let hit = rapier_context.single().cast_ray(origin, direction, LASER_RANGE, true, solid_ray_filter(ship_entity));
+
+    let distance = hit.map_or(LASER_RANGE, |(_, toi)| toi);
+    let end = origin + direction * distance;
+    beam_transform.translation = (origin + end) / 2.0;
+    beam_transform.rotation = Quat::from_rotation_arc(Vec3::Y, direction);
+    beam_transform.scale = Vec3::new(1.0, distance, 1.0);
+    *beam_visibility = Visibility::Visible;
+
+    let Some((target, _)) = hit else {
+        return;
+    };
+    // Flicker the impact glow so a sustained hit reads as burning
+    impact_transform.translation = end;
+    impact_transform.scale = Vec3::splat(1.0 + 0.3 * (time.elapsed_secs() * 30.0).sin());
+    *impact_visibility = Visibility::Visible;
+
+    let Ok((target_faction, has_health)) = target_query.get(target) else {
+        return;
+    };
+    if relations.allows(*ship_faction, target_faction) && has_health {
+        damage_events.send(DamageDealt {
+            target,
+            amount: LASER_DAMAGE_PER_SECOND * delta,
+            source: Some(ship_entity),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fire_drains_and_idle_recharges() {
+        let mut energy = LaserEnergy::default();
+        assert!(energy.fire(1.0, true));
+        assert_eq!(energy.current, LASER_ENERGY - LASER_DRAIN_RATE);
+        assert!(!energy.fire(1.0, false));
+        assert_eq!(energy.current, LASER_ENERGY - LASER_DRAIN_RATE + LASER_RECHARGE_RATE);
+    }
+
+    #[test]
+    fn emptied_laser_waits_for_refire_energy() {
+        let mut energy = LaserEnergy::default();
+        assert!(energy.fire(LASER_ENERGY / LASER_DRAIN_RATE, true));
+        assert_eq!(energy.current, 0.0);
+        // Holding the trigger only recharges until the refire threshold is reached
+        let step = 0.1;
+        let mut held = 0.0;
+        while !energy.fire(step, true) {
+            held += step;
+            assert!(held < 10.0, "the laser never fired again");
+        }
+        assert!(energy.current + LASER_DRAIN_RATE * step >= LASER_REFIRE_ENERGY);
+    }
+}
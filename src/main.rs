@@ -1,48 +1,111 @@
 //! Illustrates different lights of various types and colors, some static, some moving over
 //! a simple scene.
 
+mod args;
 mod asset;
+mod bot;
 mod bullet;
 mod camera;
 mod game;
 mod mech;
+mod missile;
 mod planet;
 mod rock;
+mod sector;
 mod spaceship;
+mod starfield;
+mod starsystem;
+mod station;
 mod enemy;
+mod explosion;
 mod crosshair;
 mod effects;
+mod faction;
+mod headless;
+mod health;
+mod impact;
+mod input;
+mod laser;
+mod replay;
+mod rng;
+mod wave;
+mod weapon;
 
+use crate::args::Args;
 use crate::asset::AssetLoaderPlugin;
 use crate::bullet::BulletPlugin;
 use crate::camera::CameraPlugin;
 use crate::enemy::EnemyPlugin;
 use crate::game::GamePlugin;
 use crate::mech::MecPlugin;
+use crate::missile::MissilePlugin;
 use crate::planet::PlanetPlugin;
 use crate::rock::RockPlugin;
+use crate::sector::SectorPlugin;
 use crate::spaceship::SpaceshipPlugin;
+use crate::starfield::StarfieldPlugin;
+use crate::starsystem::StarSystemPlugin;
+use crate::station::StationPlugin;
 use bevy::prelude::*;
 use bevy::render::render_resource::CachedPipelineState::Creating;
 use bevy_rapier3d::prelude::*;
 use crate::crosshair::CrossHairPlugin;
 use crate::effects::EffectsPlugin;
+use crate::explosion::ExplosionPlugin;
+use crate::faction::FactionPlugin;
+use crate::headless::HeadlessOptions;
+use crate::health::HealthPlugin;
+use crate::impact::ImpactPlugin;
+use crate::input::InputPlugin;
+use crate::laser::LaserPlugin;
+use crate::replay::{ReplayMode, ReplayPlugin};
+use crate::rng::{seed_from_args, RngPlugin};
+use crate::wave::WavePlugin;
+use crate::weapon::WeaponPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let args = Args::from_env();
+    let replay_mode = ReplayMode::from_args(&args);
+    let seed = replay_mode.seed().or_else(|| seed_from_args(&args));
+    if let Some(options) = HeadlessOptions::from_args(&args, &replay_mode) {
+        headless::run(options, seed, replay_mode);
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
         //.add_plugins(RapierDebugRenderPlugin::default())
         .add_plugins(CameraPlugin)
         .add_plugins(AssetLoaderPlugin)
-        .add_plugins(SpaceshipPlugin)
+        .add_plugins(CrossHairPlugin)
+        .add_plugins(EffectsPlugin)
+        .add_plugins(StarfieldPlugin);
+    add_gameplay_plugins(&mut app, seed, replay_mode);
+    app.run();
+}
+
+/// Plugins that make up the simulation itself, shared by the game and the headless runner.
+fn add_gameplay_plugins(app: &mut App, seed: Option<u64>, replay_mode: ReplayMode) {
+    app.add_plugins(SpaceshipPlugin)
         .add_plugins(RockPlugin)
         .add_plugins(MecPlugin)
         .add_plugins(BulletPlugin)
+        .add_plugins(LaserPlugin)
+        .add_plugins(WeaponPlugin)
+        .add_plugins(MissilePlugin)
         .add_plugins(PlanetPlugin)
+        .add_plugins(StarSystemPlugin)
+        .add_plugins(StationPlugin)
+        .add_plugins(SectorPlugin)
         .add_plugins(GamePlugin)
         .add_plugins(EnemyPlugin)
-        .add_plugins(CrossHairPlugin)
-        .add_plugins(EffectsPlugin)
-        .run();
+        .add_plugins(HealthPlugin)
+        .add_plugins(ImpactPlugin)
+        .add_plugins(ExplosionPlugin)
+        .add_plugins(FactionPlugin)
+        .add_plugins(WavePlugin)
+        .add_plugins(RngPlugin { seed })
+        .add_plugins(InputPlugin)
+        .add_plugins(ReplayPlugin { mode: replay_mode });
 }
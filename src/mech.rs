@@ -1,11 +1,17 @@
 use crate::asset::SpaceKit;
-use bevy::app::{App, Plugin, Startup, Update};
+use crate::faction::Faction;
+use crate::game::{AppState, InGame};
+use crate::health::Health;
+use crate::rng::{random_direction, GameRng, RngStream};
+use bevy::app::{App, Plugin, Update};
 use bevy::asset::Assets;
 use bevy::math::Vec3;
 use bevy::prelude::*;
 use bevy_rapier3d::dynamics::{Damping, ExternalForce, GravityScale, RigidBody};
 use bevy_rapier3d::geometry::Collider;
-use rand::{random, random_range};
+use rand::Rng;
+
+const MECH_HEALTH: f32 = 30.0;
 
 pub struct MecPlugin;
 
@@ -18,64 +24,55 @@ pub struct RandomFlight {
 pub struct Mech;
 impl Plugin for MecPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (spawn_mech))
-            .add_systems(Update, random_flight_system);
+        app.add_systems(Update, random_flight_system.run_if(in_state(AppState::Playing)));
     }
 }
 
-fn spawn_mech(
-    mut commands: Commands,
-    space_kit: Res<SpaceKit>,
-    mut meshes: ResMut<Assets<Mesh>>,
+pub fn spawn_mech(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    rng: &mut impl Rng,
+    transform: Transform,
 ) {
-    for i in 1..1000 {
-        let index = random_range(0..space_kit.mechs.len());
-        let scene_root = SceneRoot(space_kit.mechs.get(index).unwrap().clone());
-        let x: f32 = random_range(-2000..2000) as f32;
-        let y: f32 = random_range(-2000..2000) as f32;
-        let z: f32 = random_range(-2000..2000) as f32;
-        commands.spawn((
-            scene_root,
-            Transform::from_xyz(x, y, z)
-                .looking_at(Vec3::from_array([0., 0., 0.]), Vec3::Y),
-            ExternalForce::default(),
-            Damping {
-                linear_damping: 0.5,
-                angular_damping: 1.0,
-            },
-            RigidBody::Dynamic,
-            Collider::ball(2.),
-            GravityScale(0.),
-            Mesh3d(meshes.add(Capsule3d::default())),
-            Mech,
-            RandomFlight {
-                direction: Vec3::new(
-                    random::<f32>() * 2.0 - 1.0,
-                    random::<f32>() * 2.0 - 1.0,
-                    random::<f32>() * 2.0 - 1.0,
-                ).normalize(),
-                timer: Timer::from_seconds(3.0, TimerMode::Repeating),
-            }
-        ));
-    }
+    let index = rng.random_range(0..space_kit.mechs.len());
+    let scene_root = SceneRoot(space_kit.mechs.get(index).unwrap().clone());
+    commands.spawn((
+        scene_root,
+        transform,
+        ExternalForce::default(),
+        Damping {
+            linear_damping: 0.5,
+            angular_damping: 1.0,
+        },
+        RigidBody::Dynamic,
+        Collider::ball(2.),
+        GravityScale(0.),
+        Mesh3d(meshes.add(Capsule3d::default())),
+        Mech,
+        Faction::Enemy,
+        Health::new(MECH_HEALTH),
+        StateScoped(InGame),
+        RandomFlight {
+            direction: random_direction(rng),
+            timer: Timer::from_seconds(3.0, TimerMode::Repeating),
+        }
+    ));
 }
 
 fn random_flight_system(
     time: Res<Time>,
+    mut rng: ResMut<GameRng>,
     mut query: Query<(&mut ExternalForce, &mut RandomFlight)>,
 ) {
+    let rng = rng.stream(RngStream::Ai);
     for (mut force, mut flight) in query.iter_mut() {
         // Update timer
         flight.timer.tick(time.delta());
 
         // If it's time, change direction randomly
         if flight.timer.finished() {
-            flight.direction = Vec3::new(
-                random::<f32>() * 2.0 - 1.0,
-                random::<f32>() * 2.0 - 1.0,
-                random::<f32>() * 2.0 - 1.0,
-            )
-                .normalize();
+            flight.direction = random_direction(rng);
         }
 
         // Apply force in current direction
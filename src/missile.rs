@@ -0,0 +1,557 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use bevy::app::{App, Plugin};
+use bevy::audio::Pitch;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use crate::bullet::{solid_ray_filter, Bullet};
+use crate::enemy::Enemy;
+use crate::explosion::Explosion;
+use crate::faction::{Faction, FactionRelations};
+use crate::game::{AppState, InGame};
+use crate::health::{DamageDealt, Destroyed, Health};
+use crate::input::{InputSet, PilotActions};
+use crate::spaceship::SpaceShip;
+
+/// Distance the missile needs to get within its target before its warhead goes off.
+const FUSE_RADIUS: f32 = 8.0;
+const BLAST_RADIUS: f32 = 20.0;
+/// How big the warhead's explosion looks and pushes, as the radius of an exploding object.
+const WARHEAD_SIZE: f32 = 1.5;
+/// Seconds a missile keeps drifting after its fuel runs out before it self-destructs.
+const COAST_TIME: f32 = 3.0;
+const LAUNCH_OFFSET: f32 = 10.0;
+/// Radius of the sensor that sets the warhead off when the missile flies into something.
+const MISSILE_RADIUS: f32 = 1.0;
+/// Seconds between warning beeps while an enemy is locking on, and once its missile is on
+/// the way.
+const LOCK_BEEP_INTERVAL: f32 = 0.6;
+const INCOMING_BEEP_INTERVAL: f32 = 0.2;
+
+/// Flight and lock-on characteristics of one kind of missile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MissileSpec {
+    /// Seconds a target has to stay in the seeker cone before it is locked.
+    pub lock_time: f32,
+    pub lock_range: f32,
+    /// Half angle in degrees of the cone in front of the launcher a target must be in.
+    pub lock_cone: f32,
+    /// Seconds between launches.
+    pub reload_time: f32,
+    pub speed: f32,
+    /// Radians per second the missile can turn.
+    pub turn_rate: f32,
+    /// Seconds of powered, steering flight.
+    pub fuel: f32,
+    pub damage: f32,
+}
+
+const PLAYER_MISSILE: MissileSpec = MissileSpec {
+    lock_time: 1.5,
+    lock_range: 900.0,
+    lock_cone: 15.0,
+    reload_time: 2.0,
+    speed: 350.0,
+    turn_rate: 2.5,
+    fuel: 6.0,
+    damage: 60.0,
+};
+
+const ENEMY_MISSILE: MissileSpec = MissileSpec {
+    lock_time: 3.0,
+    lock_range: 700.0,
+    lock_cone: 30.0,
+    reload_time: 10.0,
+    speed: 180.0,
+    turn_rate: 1.2,
+    fuel: 8.0,
+    damage: 25.0,
+};
+
+/// Seeker and launch rail of a ship that can fire missiles.
+#[derive(Component, Debug)]
+pub struct MissileLauncher {
+    pub spec: MissileSpec,
+    pub target: Option<Entity>,
+    /// Seconds the current target has been held in the seeker cone.
+    pub lock_progress: f32,
+    /// Hostile picked with target-next. While set, the seeker ignores everything else.
+    pub selected: Option<Entity>,
+    reload: f32,
+}
+
+impl MissileLauncher {
+    pub fn new(spec: MissileSpec) -> Self {
+        MissileLauncher {
+            spec,
+            target: None,
+            lock_progress: 0.0,
+            selected: None,
+            reload: 0.0,
+        }
+    }
+
+    pub fn player() -> Self {
+        MissileLauncher::new(PLAYER_MISSILE)
+    }
+
+    pub fn enemy() -> Self {
+        MissileLauncher::new(ENEMY_MISSILE)
+    }
+
+    pub fn locked(&self) -> bool {
+        self.target.is_some() && self.lock_progress >= self.spec.lock_time
+    }
+
+    /// Keeps building the lock while the seeker sees the same target, starts over otherwise.
+    fn track(&mut self, candidate: Option<Entity>, delta: f32) {
+        if candidate.is_some() && candidate == self.target {
+            self.lock_progress = (self.lock_progress + delta).min(self.spec.lock_time);
+        } else {
+            self.target = candidate;
+            self.lock_progress = 0.0;
+        }
+    }
+
+    fn release(&mut self) {
+        self.target = None;
+        self.lock_progress = 0.0;
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Missile {
+    pub target: Option<Entity>,
+    pub owner: Entity,
+    pub faction: Faction,
+    spec: MissileSpec,
+    fuel: f32,
+}
+
+#[derive(Resource)]
+struct MissileAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Tones of the lock warning, played as generated sine waves.
+#[derive(Resource)]
+struct LockWarningSounds {
+    locking: Handle<Pitch>,
+    incoming: Handle<Pitch>,
+}
+
+#[derive(Component)]
+struct LockText;
+
+#[derive(Component)]
+struct LockWarningText;
+
+pub struct MissilePlugin;
+
+impl Plugin for MissilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (load_missile_assets, load_lock_warning_sounds))
+            .add_systems(OnEnter(InGame), spawn_lock_ui)
+            .add_systems(Update, (
+                (select_target, lock_player_missiles, lock_enemy_missiles, guide_missiles).chain().after(InputSet::Override),
+                update_lock_ui,
+                sound_lock_warning.run_if(resource_exists::<LockWarningSounds>),
+            ).run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn load_missile_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MissileAssets {
+        mesh: meshes.add(Cuboid::new(0.6, 0.6, 3.0)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.8, 0.3),
+            emissive: LinearRgba::from(Color::srgb(6.0, 3.0, 0.5)),
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn load_lock_warning_sounds(mut commands: Commands, pitches: Option<ResMut<Assets<Pitch>>>) {
+    // There is no audio when running headless
+    let Some(mut pitches) = pitches else {
+        return;
+    };
+    commands.insert_resource(LockWarningSounds {
+        locking: pitches.add(Pitch::new(880.0, Duration::from_millis(80))),
+        incoming: pitches.add(Pitch::new(1320.0, Duration::from_millis(60))),
+    });
+}
+
+/// Whether nothing but `target` lies on the straight line from `origin` to it.
+fn in_line_of_sight(
+    rapier_context: &RapierContext,
+    origin: Vec3,
+    target: Vec3,
+    target_entity: Entity,
+    exclude: Entity,
+) -> bool {
+    let offset = target - origin;
+    match rapier_context.cast_ray(origin, offset.normalize_or_zero(), offset.length(), true, solid_ray_filter(exclude)) {
+        Some((hit, _)) => hit == target_entity,
+        None => true,
+    }
+}
+
+/// The candidate inside the seeker cone closest to its centre.
+fn seek(
+    spec: &MissileSpec,
+    origin: Vec3,
+    nose: Vec3,
+    candidates: impl Iterator<Item = (Entity, Vec3)>,
+) -> Option<(Entity, Vec3)> {
+    let min_alignment = spec.lock_cone.to_radians().cos();
+    candidates
+        .filter(|(_, position)| origin.distance(*position) < spec.lock_range)
+        .map(|(entity, position)| (entity, position, (position - origin).normalize_or_zero().dot(nose)))
+        .filter(|(_, _, alignment)| *alignment >= min_alignment)
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(entity, position, _)| (entity, position))
+}
+
+fn launch_missile(
+    commands: &mut Commands,
+    missile_assets: &MissileAssets,
+    launcher: &mut MissileLauncher,
+    owner: Entity,
+    faction: Faction,
+    origin: Vec3,
+    nose: Vec3,
+) {
+    let spec = launcher.spec;
+    commands.spawn((
+        Mesh3d(missile_assets.mesh.clone()),
+        MeshMaterial3d(missile_assets.material.clone()),
+        Transform::from_translation(origin + nose * LAUNCH_OFFSET).looking_to(nose, Vec3::Y),
+        RigidBody::KinematicVelocityBased,
+        Velocity::linear(nose * spec.speed),
+        Collider::ball(MISSILE_RADIUS),
+        Sensor,
+        ActiveEvents::COLLISION_EVENTS,
+        // Kinematic bodies only report touching dynamic ones unless told otherwise
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+        Missile {
+            target: launcher.target,
+            owner,
+            faction,
+            spec,
+            fuel: spec.fuel,
+        },
+        StateScoped(InGame),
+    ));
+    launcher.release();
+    launcher.reload = spec.reload_time;
+}
+
+/// Steps the player's selected target through the hostiles in missile range, nearest first,
+/// and back to no selection after the last one.
+fn select_target(
+    actions: Res<PilotActions>,
+    relations: Res<FactionRelations>,
+    mut ship_query: Query<(&Transform, &Faction, &mut MissileLauncher), With<SpaceShip>>,
+    target_query: Query<(Entity, &Transform, &Faction), (With<Health>, Without<SpaceShip>)>,
+) {
+    let Ok((ship_transform, ship_faction, mut launcher)) = ship_query.get_single_mut() else {
+        return;
+    };
+    if launcher.selected.is_some_and(|selected| !target_query.contains(selected)) {
+        launcher.selected = None;
+    }
+    if !actions.target_next {
+        return;
+    }
+    let origin = ship_transform.translation;
+    let mut candidates: Vec<(Entity, f32)> = target_query
+        .iter()
+        .filter(|(_, _, faction)| relations.is_hostile(*ship_faction, **faction))
+        .map(|(entity, transform, _)| (entity, origin.distance(transform.translation)))
+        .filter(|(_, distance)| *distance < launcher.spec.lock_range)
+        .collect();
+    candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let next = launcher.selected
+        .and_then(|selected| candidates.iter().position(|(entity, _)| *entity == selected))
+        .map_or(0, |index| index + 1);
+    launcher.selected = candidates.get(next).map(|(entity, _)| *entity);
+}
+
+/// The player holds the missile button to lock onto the hostile nearest the nose and
+/// launches by letting go once the lock is complete.
+fn lock_player_missiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    actions: Res<PilotActions>,
+    relations: Res<FactionRelations>,
+    missile_assets: Res<MissileAssets>,
+    rapier_context: ReadRapierContext,
+    mut ship_query: Query<(Entity, &Transform, &Faction, &mut MissileLauncher), With<SpaceShip>>,
+    target_query: Query<(Entity, &Transform, &Faction), (With<Health>, Without<SpaceShip>)>,
+) {
+    let Ok((ship_entity, ship_transform, ship_faction, mut launcher)) = ship_query.get_single_mut() else {
+        return;
+    };
+    let delta = time.delta_secs();
+    launcher.reload = (launcher.reload - delta).max(0.0);
+    if !actions.missile {
+        if launcher.locked() {
+            // The ship model's nose is its local +Z axis
+            let nose = ship_transform.rotation * Vec3::Z;
+            launch_missile(&mut commands, &missile_assets, &mut launcher, ship_entity, *ship_faction, ship_transform.translation, nose);
+        }
+        launcher.release();
+        return;
+    }
+    if launcher.reload > 0.0 {
+        return;
+    }
+
+    let rapier_context = rapier_context.single();
+    let origin = ship_transform.translation;
+    let selected = launcher.selected;
+    let candidates = target_query
+        .iter()
+        .filter(|(entity, _, faction)| {
+            relations.is_hostile(*ship_faction, **faction) && selected.is_none_or(|selected| selected == *entity)
+        })
+        .map(|(entity, transform, _)| (entity, transform.translation));
+    let candidate = seek(&launcher.spec, origin, ship_transform.rotation * Vec3::Z, candidates)
+        .filter(|(entity, position)| in_line_of_sight(&rapier_context, origin, *position, *entity, ship_entity))
+        .map(|(entity, _)| entity);
+    launcher.track(candidate, delta);
+}
+
+/// Enemies lock onto the player whenever it is in front of them and fire as soon as the
+/// lock completes.
+fn lock_enemy_missiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    missile_assets: Res<MissileAssets>,
+    rapier_context: ReadRapierContext,
+    ship_query: Query<(Entity, &Transform, Has<Destroyed>), With<SpaceShip>>,
+    mut enemy_query: Query<(Entity, &Transform, &mut MissileLauncher), (With<Enemy>, Without<SpaceShip>)>,
+) {
+    let Ok((ship_entity, ship_transform, destroyed)) = ship_query.get_single() else {
+        return;
+    };
+    let player = (!destroyed).then_some((ship_entity, ship_transform.translation));
+    let rapier_context = rapier_context.single();
+    let delta = time.delta_secs();
+    for (enemy_entity, enemy_transform, mut launcher) in enemy_query.iter_mut() {
+        launcher.reload = (launcher.reload - delta).max(0.0);
+        if launcher.reload > 0.0 {
+            continue;
+        }
+        // Enemies turn to face the player, so their nose is `forward`
+        let origin = enemy_transform.translation;
+        let nose = enemy_transform.forward().as_vec3();
+        let candidate = seek(&launcher.spec, origin, nose, player.into_iter())
+            .filter(|(entity, position)| in_line_of_sight(&rapier_context, origin, *position, *entity, enemy_entity))
+            .map(|(entity, _)| entity);
+        launcher.track(candidate, delta);
+        if launcher.locked() {
+            launch_missile(&mut commands, &missile_assets, &mut launcher, enemy_entity, Faction::Enemy, origin, nose);
+        }
+    }
+}
+
+/// Steers missiles towards where their target will be, detonates them near it or in
+/// whatever they fly into and drops the target once something blocks the line of sight.
+fn guide_missiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    relations: Res<FactionRelations>,
+    rapier_context: ReadRapierContext,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageDealt>,
+    mut explosion_events: EventWriter<Explosion>,
+    mut missile_query: Query<(Entity, &mut Transform, &mut Velocity, &mut Missile)>,
+    target_query: Query<(&Transform, Option<&Velocity>), Without<Missile>>,
+    victim_query: Query<(Entity, &Transform, Option<&Faction>), (With<Health>, Without<Missile>)>,
+    bullet_query: Query<(), With<Bullet>>,
+) {
+    let rapier_context = rapier_context.single();
+    // Bullets and other missiles pass through, as does the launcher while the missile clears it
+    let struck: HashSet<Entity> = collision_events
+        .read()
+        .filter_map(|event| match event {
+            CollisionEvent::Started(e1, e2, _) => Some([(*e1, *e2), (*e2, *e1)]),
+            CollisionEvent::Stopped(_, _, _) => None,
+        })
+        .flatten()
+        .filter(|(missile, other)| {
+            missile_query.get(*missile).is_ok_and(|(_, _, _, missile)| missile.owner != *other)
+                && !missile_query.contains(*other)
+                && !bullet_query.contains(*other)
+        })
+        .map(|(missile, _)| missile)
+        .collect();
+    let delta = time.delta_secs();
+    for (entity, mut transform, mut velocity, mut missile) in missile_query.iter_mut() {
+        missile.fuel -= delta;
+        if missile.fuel < -COAST_TIME {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let position = transform.translation;
+        let target = missile.target.and_then(|target| target_query.get(target).ok().map(|found| (target, found)));
+        let in_fuse_range = target.is_some_and(|(_, (target_transform, _))| position.distance(target_transform.translation) < FUSE_RADIUS);
+        if in_fuse_range || struck.contains(&entity) {
+            // Everything in the blast takes damage, as long as the missile's faction may hurt it
+            for (victim, victim_transform, victim_faction) in victim_query.iter() {
+                if relations.allows(missile.faction, victim_faction) && victim != missile.owner && victim_transform.translation.distance(position) < BLAST_RADIUS {
+                    damage_events.send(DamageDealt {
+                        target: victim,
+                        amount: missile.spec.damage,
+                        source: Some(missile.owner),
+                    });
+                }
+            }
+            explosion_events.send(Explosion {
+                position,
+                size: WARHEAD_SIZE,
+            });
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let Some((target_entity, (target_transform, target_velocity))) = target else {
+            missile.target = None;
+            continue;
+        };
+        let distance = position.distance(target_transform.translation);
+        if missile.fuel <= 0.0 {
+            continue;
+        }
+        if !in_line_of_sight(&rapier_context, position, target_transform.translation, target_entity, entity) {
+            missile.target = None;
+            continue;
+        }
+
+        // Lead the target by the time it takes to close the current distance
+        let target_linvel = target_velocity.map_or(Vec3::ZERO, |velocity| velocity.linvel);
+        let predicted = target_transform.translation + target_linvel * (distance / missile.spec.speed);
+        let current = velocity.linvel.normalize_or(transform.forward().as_vec3());
+        let desired = (predicted - position).normalize_or(current);
+        let max_turn = missile.spec.turn_rate * delta;
+        let angle = current.angle_between(desired);
+        let heading = if angle <= max_turn {
+            desired
+        } else {
+            Quat::IDENTITY.slerp(Quat::from_rotation_arc(current, desired), max_turn / angle) * current
+        };
+        velocity.linvel = heading * missile.spec.speed;
+        transform.look_to(heading, Vec3::Y);
+    }
+}
+
+fn spawn_lock_ui(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(40.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        StateScoped(InGame),
+    )).with_children(|parent| {
+        parent.spawn((Text::default(), LockText));
+    });
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            top: Val::Percent(15.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        StateScoped(InGame),
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::default(),
+            TextFont {
+                font_size: 32.0,
+                ..default()
+            },
+            TextColor(Color::srgb(1.0, 0.2, 0.2)),
+            LockWarningText,
+        ));
+    });
+}
+
+fn update_lock_ui(
+    time: Res<Time>,
+    ship_query: Query<(Entity, &Transform, &MissileLauncher), With<SpaceShip>>,
+    target_query: Query<&Transform, Without<SpaceShip>>,
+    launcher_query: Query<&MissileLauncher, Without<SpaceShip>>,
+    missile_query: Query<&Missile>,
+    mut lock_text: Query<&mut Text, (With<LockText>, Without<LockWarningText>)>,
+    mut warning_text: Query<&mut Text, (With<LockWarningText>, Without<LockText>)>,
+) {
+    let (Ok((ship_entity, ship_transform, launcher)), Ok(mut lock_text), Ok(mut warning_text)) =
+        (ship_query.get_single(), lock_text.get_single_mut(), warning_text.get_single_mut()) else {
+        return;
+    };
+
+    lock_text.0 = if launcher.locked() {
+        "MISSILE LOCKED - release to fire".to_string()
+    } else if launcher.target.is_some() {
+        format!("Locking {:.0}%", launcher.lock_progress / launcher.spec.lock_time * 100.0)
+    } else if let Some(selected) = launcher.selected.and_then(|selected| target_query.get(selected).ok()) {
+        format!("Target {:.0} m", selected.translation.distance(ship_transform.translation))
+    } else {
+        String::new()
+    };
+
+    let incoming = missile_query.iter().any(|missile| missile.target == Some(ship_entity));
+    let locking = launcher_query.iter().any(|launcher| launcher.target == Some(ship_entity));
+    // Blink twice a second so the warning stands out
+    let blink_on = time.elapsed_secs().fract() < 0.5;
+    warning_text.0 = match (incoming, locking) {
+        (true, _) if blink_on => "MISSILE INCOMING".to_string(),
+        (false, true) => "WARNING: MISSILE LOCK".to_string(),
+        _ => String::new(),
+    };
+}
+
+/// Beeps while an enemy seeker is locking onto the player, faster once its missile is on the
+/// way. The first beep sounds as soon as the lock starts.
+fn sound_lock_warning(
+    mut commands: Commands,
+    time: Res<Time>,
+    sounds: Res<LockWarningSounds>,
+    mut since_beep: Local<Option<f32>>,
+    ship_query: Query<Entity, With<SpaceShip>>,
+    launcher_query: Query<&MissileLauncher, Without<SpaceShip>>,
+    missile_query: Query<&Missile>,
+) {
+    let Ok(ship_entity) = ship_query.get_single() else {
+        return;
+    };
+    let incoming = missile_query.iter().any(|missile| missile.target == Some(ship_entity));
+    let locking = launcher_query.iter().any(|launcher| launcher.target == Some(ship_entity));
+    let (interval, sound) = match (incoming, locking) {
+        (true, _) => (INCOMING_BEEP_INTERVAL, &sounds.incoming),
+        (false, true) => (LOCK_BEEP_INTERVAL, &sounds.locking),
+        (false, false) => {
+            *since_beep = None;
+            return;
+        }
+    };
+    let elapsed = since_beep.map_or(interval, |since| since + time.delta_secs());
+    if elapsed >= interval {
+        commands.spawn((AudioPlayer(sound.clone()), PlaybackSettings::DESPAWN));
+        *since_beep = Some(0.0);
+    } else {
+        *since_beep = Some(elapsed);
+    }
+}
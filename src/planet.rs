@@ -1,43 +1,194 @@
-use bevy::math::Vec3;
 use bevy::prelude::*;
-use bevy_rapier3d::dynamics::{GravityScale, RigidBody};
-use bevy_rapier3d::geometry::Collider;
-use rand::{random, random_range};
+use bevy_rapier3d::prelude::*;
 use crate::asset::SpaceKit;
+use crate::bullet::Bullet;
+use crate::enemy::Enemy;
+use crate::game::{AppState, InGame};
+use crate::mech::Mech;
+use crate::rock::Rock;
+use crate::spaceship::SpaceShip;
 
-pub struct PlanetPlugin;
+/// Collider radius of an unscaled planet model.
+const PLANET_RADIUS: f32 = 2.0;
+/// Pull at the surface of a planet, moons included.
+const SURFACE_GRAVITY: f32 = 30.0;
+/// Gravity reaches this many planet radii from the centre.
+const GRAVITY_REACH: f32 = 6.0;
 
 #[derive(Component, Debug)]
-pub struct Planet;
+pub struct Planet {
+    /// Index into `SpaceKit::planets`.
+    pub model: usize,
+}
+
+/// Inverse-square pull towards the entity, cut off beyond `reach`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct GravityWell {
+    /// Gravitational parameter: the pull at distance `r` is `strength / r²`.
+    pub strength: f32,
+    /// Radius of the body itself. The pull stops growing below it.
+    pub surface: f32,
+    pub reach: f32,
+}
+
+impl GravityWell {
+    pub fn for_planet(scale: f32) -> Self {
+        let surface = PLANET_RADIUS * scale;
+        GravityWell {
+            strength: SURFACE_GRAVITY * surface * surface,
+            surface,
+            reach: surface * GRAVITY_REACH,
+        }
+    }
+
+    pub fn acceleration(&self, offset: Vec3) -> Vec3 {
+        let distance = offset.length();
+        if distance >= self.reach || distance == 0.0 {
+            return Vec3::ZERO;
+        }
+        -offset / distance * self.strength / distance.max(self.surface).powi(2)
+    }
+
+    /// Speed of a circular orbit at `radius` around this well.
+    pub fn orbital_speed(&self, radius: f32) -> f32 {
+        (self.strength / radius).sqrt()
+    }
+}
+
+/// Circular orbit around a fixed `center`, in the plane `tilt` turns the XZ plane into.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Orbit {
+    pub center: Vec3,
+    pub radius: f32,
+    pub angle: f32,
+    /// Radians per second.
+    pub angular_speed: f32,
+    pub tilt: Quat,
+}
+
+impl Orbit {
+    pub fn position(&self) -> Vec3 {
+        self.center + self.tilt * Vec3::new(self.angle.cos(), 0.0, self.angle.sin()) * self.radius
+    }
+}
+
+/// Turns the entity around the direction of the vector, by its length in radians per second.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Spin(pub Vec3);
+
+pub struct PlanetPlugin;
 
 impl Plugin for PlanetPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_planets);
+        app.add_systems(Update, (spin_bodies, move_orbits, apply_gravity).chain().run_if(in_state(AppState::Playing)));
     }
 }
 
-fn spawn_planets(
-    mut commands: Commands,
-    space_kit: Res<SpaceKit>,
-    mut meshes: ResMut<Assets<Mesh>>,
+/// Spawns planet model `model` of the space kit. Planets are fixed in place.
+pub fn spawn_planet(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    model: usize,
+    transform: Transform,
+) -> Entity {
+    let scene_root = SceneRoot(space_kit.planets[model % space_kit.planets.len()].clone());
+    commands.spawn((
+        scene_root,
+        transform,
+        RigidBody::Fixed,
+        Mesh3d(meshes.add(Cuboid::default())),
+        GravityScale(0.0),
+        Collider::ball(PLANET_RADIUS),
+        Planet { model },
+        GravityWell::for_planet(transform.scale.max_element()),
+        StateScoped(InGame),
+    )).id()
+}
+
+/// Spawns a moon following `orbit`. Moons push whatever is in their way and pull things in
+/// like planets do.
+pub fn spawn_moon(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    model: usize,
+    scale: f32,
+    orbit: Orbit,
+) -> Entity {
+    let transform = Transform::from_translation(orbit.position()).with_scale(Vec3::splat(scale));
+    let moon = spawn_planet(commands, space_kit, meshes, model, transform);
+    commands.entity(moon).insert((RigidBody::KinematicPositionBased, orbit));
+    moon
+}
+
+fn spin_bodies(time: Res<Time>, mut spin_query: Query<(&mut Transform, &Spin)>) {
+    for (mut transform, spin) in spin_query.iter_mut() {
+        transform.rotate(Quat::from_scaled_axis(spin.0 * time.delta_secs()));
+    }
+}
+
+fn move_orbits(time: Res<Time>, mut orbit_query: Query<(&mut Transform, &mut Orbit)>) {
+    for (mut transform, mut orbit) in orbit_query.iter_mut() {
+        orbit.angle = (orbit.angle + orbit.angular_speed * time.delta_secs()) % std::f32::consts::TAU;
+        transform.translation = orbit.position();
+    }
+}
+
+/// Pulls ships, rocks, mechs and bullets towards every star, planet and moon whose reach they
+/// are in.
+fn apply_gravity(
+    time: Res<Time>,
+    well_query: Query<(&Transform, &GravityWell)>,
+    mut body_query: Query<
+        (&Transform, &mut Velocity),
+        (Or<(With<SpaceShip>, With<Enemy>, With<Mech>, With<Rock>, With<Bullet>)>, Without<RigidBodyDisabled>),
+    >,
 ) {
-    for planet_handle in space_kit.planets.iter() {
-        let scene_root = SceneRoot(planet_handle.clone());
-        let x = random_range(-8000.0..8000.);
-        let y = random_range(-8000.0..8000.);
-        let z = random_range(-8000.0..8000.);
-        let scale = 300.0;
-        commands.spawn((
-            scene_root,
-            Transform {
-                translation: Vec3::new(x, y, z),
-                scale: Vec3::splat(scale),
-                ..default()
-            },
-            RigidBody::Fixed,
-            Mesh3d(meshes.add(Cuboid::default())),
-            GravityScale(0.0),
-            Collider::ball(scale / 150.),
-        ));
-    }
-}
\ No newline at end of file
+    let delta = time.delta_secs();
+    for (transform, mut velocity) in body_query.iter_mut() {
+        let acceleration: Vec3 = well_query
+            .iter()
+            .map(|(well_transform, well)| well.acceleration(transform.translation - well_transform.translation))
+            .sum();
+        velocity.linvel += acceleration * delta;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pull_points_at_the_well_and_falls_off_with_distance_squared() {
+        let well = GravityWell::for_planet(10.0);
+        let near = well.acceleration(Vec3::X * well.surface * 2.0);
+        let far = well.acceleration(Vec3::X * well.surface * 4.0);
+        assert!(near.x < 0.0 && near.y == 0.0 && near.z == 0.0);
+        assert!((near.length() / far.length() - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pull_at_the_surface_is_surface_gravity_and_stops_growing_inside() {
+        let well = GravityWell::for_planet(10.0);
+        let surface = well.acceleration(Vec3::Y * well.surface).length();
+        assert!((surface - SURFACE_GRAVITY).abs() < 1e-3);
+        assert!((well.acceleration(Vec3::Y * well.surface / 2.0).length() - surface).abs() < 1e-3);
+    }
+
+    #[test]
+    fn no_pull_beyond_reach_or_at_the_centre() {
+        let well = GravityWell::for_planet(10.0);
+        assert_eq!(well.acceleration(Vec3::Z * well.reach), Vec3::ZERO);
+        assert_eq!(well.acceleration(Vec3::ZERO), Vec3::ZERO);
+    }
+
+    #[test]
+    fn orbital_speed_balances_the_pull() {
+        let well = GravityWell::for_planet(10.0);
+        let radius = well.surface * 3.0;
+        let speed = well.orbital_speed(radius);
+        // Centripetal acceleration of a circular orbit is v² / r
+        assert!((speed * speed / radius - well.acceleration(Vec3::X * radius).length()).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use bevy::app::{App, AppExit, Plugin};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::args::Args;
+use crate::asset::load_ron;
+use crate::game::{AppState, GameState, InGame};
+use crate::input::{InputSet, PilotActions};
+use crate::rng::GameRng;
+
+/// Simulation ticks per second while recording or replaying.
+pub const TICK_RATE: f64 = 60.0;
+
+/// A recorded session: the world seed plus the pilot's actions for every tick of the round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u64,
+    pub tick_rate: f64,
+    pub frames: Vec<PilotActions>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        load_ron(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ReplayMode {
+    Off,
+    Record(PathBuf),
+    Replay(Recording),
+}
+
+impl ReplayMode {
+    /// Picks the mode from `--record <file>` or `--replay <file>` on the command line.
+    pub fn from_args(args: &Args) -> Self {
+        if let Some(path) = args.value_of("--replay") {
+            match Recording::load(Path::new(path)) {
+                Ok(recording) => return ReplayMode::Replay(recording),
+                Err(error) => panic!("Could not load replay {}", error),
+            }
+        }
+        match args.value_of("--record") {
+            Some(path) => ReplayMode::Record(PathBuf::from(path)),
+            None => ReplayMode::Off,
+        }
+    }
+
+    /// Seed the world has to be generated from, if the mode dictates one.
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            ReplayMode::Replay(recording) => Some(recording.seed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+struct Recorder {
+    path: PathBuf,
+    recording: Recording,
+}
+
+#[derive(Resource, Debug)]
+struct Replayer {
+    recording: Recording,
+    tick: usize,
+}
+
+pub struct ReplayPlugin {
+    pub mode: ReplayMode,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let tick_rate = match &self.mode {
+            ReplayMode::Off => return,
+            ReplayMode::Record(path) => {
+                app.insert_resource(Recorder {
+                    path: path.clone(),
+                    recording: Recording {
+                        seed: 0,
+                        tick_rate: TICK_RATE,
+                        frames: Vec::new(),
+                    },
+                })
+                    .add_systems(OnEnter(InGame), start_recording)
+                    .add_systems(Update, record_actions.after(InputSet::Override).run_if(in_state(AppState::Playing)))
+                    .add_systems(OnEnter(AppState::GameOver), save_recording)
+                    .add_systems(Last, save_recording.run_if(on_event::<AppExit>));
+                TICK_RATE
+            }
+            ReplayMode::Replay(recording) => {
+                app.insert_resource(Replayer {
+                    recording: recording.clone(),
+                    tick: 0,
+                })
+                    .add_systems(OnEnter(InGame), rewind_replay)
+                    .add_systems(Update, start_replay.run_if(in_state(AppState::Menu)))
+                    .add_systems(Update, replay_actions.in_set(InputSet::Override).run_if(in_state(AppState::Playing)))
+                    .add_systems(OnEnter(AppState::GameOver), report_replay);
+                recording.tick_rate
+            }
+        };
+
+        // Every frame advances the simulation by exactly one tick so runs line up frame for frame
+        let tick = Duration::from_secs_f64(1.0 / tick_rate);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+            .insert_resource(TimestepMode::Fixed {
+                dt: tick.as_secs_f32(),
+                substeps: 1,
+            });
+    }
+}
+
+fn start_recording(mut recorder: ResMut<Recorder>, rng: Res<GameRng>) {
+    recorder.recording.seed = rng.seed();
+    recorder.recording.frames.clear();
+}
+
+fn record_actions(mut recorder: ResMut<Recorder>, actions: Res<PilotActions>) {
+    recorder.recording.frames.push(*actions);
+}
+
+fn save_recording(recorder: Res<Recorder>) {
+    match recorder.recording.save(&recorder.path) {
+        Ok(()) => info!(
+            "Recorded {} ticks to {}",
+            recorder.recording.frames.len(),
+            recorder.path.display()
+        ),
+        Err(error) => warn!("Could not save recording {}", error),
+    }
+}
+
+fn rewind_replay(mut replayer: ResMut<Replayer>) {
+    replayer.tick = 0;
+}
+
+fn start_replay(mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(AppState::Playing);
+}
+
+fn replay_actions(
+    mut replayer: ResMut<Replayer>,
+    mut actions: ResMut<PilotActions>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    match replayer.recording.frames.get(replayer.tick) {
+        Some(frame) => {
+            *actions = *frame;
+            replayer.tick += 1;
+        }
+        None => {
+            *actions = PilotActions::default();
+            next_state.set(AppState::GameOver);
+        }
+    }
+}
+
+fn report_replay(replayer: Res<Replayer>, game_state: Res<GameState>) {
+    info!(
+        "Replay finished after {} of {} ticks with score {}",
+        replayer.tick,
+        replayer.recording.frames.len(),
+        game_state.score
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::headless::{simulate, HeadlessOptions, Pilot};
+    use super::*;
+
+    const SEED: u64 = 1234;
+    const TICKS: u32 = 1200;
+
+    /// A run recorded with the bot at the controls plays back to the same result.
+    #[test]
+    fn replay_reaches_recorded_score() {
+        let path = std::env::temp_dir().join("space_shooter_replay_test.ron");
+        let (recorded_app, recorded) = simulate(
+            HeadlessOptions { ticks: TICKS, pilot: Pilot::Bot },
+            Some(SEED),
+            ReplayMode::Record(path.clone()),
+        );
+        let _ = fs::remove_file(&path);
+        // Only the round being played is recorded
+        assert_eq!(recorded.rounds, 1, "the bot lost its ship, record fewer ticks");
+        let recording = recorded_app.world().resource::<Recorder>().recording.clone();
+        assert_eq!(recording.seed, SEED);
+        assert_eq!(recording.frames.len(), TICKS as usize);
+
+        let (_, replayed) = simulate(
+            HeadlessOptions { ticks: TICKS, pilot: Pilot::Replay },
+            Some(recording.seed),
+            ReplayMode::Replay(recording),
+        );
+        assert_eq!(replayed.score, recorded.score);
+        assert_eq!(replayed.kills, recorded.kills);
+        assert_eq!(replayed.deaths, recorded.deaths);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::args::Args;
+use crate::game::InGame;
+
+/// Independent random streams, so that e.g. extra particles never shift where enemies spawn.
+/// Systems that share a stream must run in a fixed order, otherwise what each one draws
+/// depends on how the scheduler happened to run them and seeded runs stop repeating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RngStream {
+    World,
+    Stars,
+    Waves,
+    Ai,
+    Rocks,
+    Effects,
+    PlayerWeapons,
+    EnemyWeapons,
+    TurretWeapons,
+}
+
+/// Seeded source of all randomness in the simulation. Every stream is reset when a round
+/// ends, so each round played with a seed starts from the same state.
+#[derive(Resource, Debug)]
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<RngStream, StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams
+            .entry(stream)
+            .or_insert_with(|| StdRng::seed_from_u64(stream_seed(seed, stream)))
+    }
+
+    /// Generator for the contents of one sector of the world. It depends only on the seed and
+    /// the sector, so a sector comes out the same no matter when or in what order it is visited.
+    pub fn sector(&self, sector: IVec3) -> StdRng {
+        let seed = sector.to_array().iter().fold(stream_seed(self.seed, RngStream::World), |seed, coordinate| {
+            (seed ^ *coordinate as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(31)
+        });
+        StdRng::seed_from_u64(seed)
+    }
+
+    pub fn reset(&mut self) {
+        self.streams.clear();
+    }
+}
+
+fn stream_seed(seed: u64, stream: RngStream) -> u64 {
+    // Spread stream indices across the seed space (golden ratio increment, as in SplitMix64)
+    seed.wrapping_add((stream as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// A random unit vector, for spawn offsets, scattering and tumbling.
+pub fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    Vec3::new(
+        rng.random_range(-1.0..1.0),
+        rng.random_range(-1.0..1.0),
+        rng.random_range(-1.0..1.0),
+    ).normalize_or(Vec3::Y)
+}
+
+/// Reads the world seed from `--seed <n>` on the command line or the `SPACE_SHOOTER_SEED`
+/// environment variable.
+pub fn seed_from_args(args: &Args) -> Option<u64> {
+    args.value_of("--seed")
+        .map(String::from)
+        .or_else(|| std::env::var("SPACE_SHOOTER_SEED").ok())
+        .and_then(|seed| seed.parse().ok())
+}
+
+pub struct RngPlugin {
+    pub seed: Option<u64>,
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        app.insert_resource(GameRng::new(seed))
+            .add_systems(Startup, print_seed)
+            .add_systems(OnExit(InGame), reset_streams);
+    }
+}
+
+fn print_seed(rng: Res<GameRng>) {
+    info!("World seed: {} (pass --seed {} to replay this world)", rng.seed(), rng.seed());
+}
+
+fn reset_streams(mut rng: ResMut<GameRng>) {
+    rng.reset();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_streams() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        // Drawing from one stream leaves the others untouched
+        a.stream(RngStream::Effects).random::<u64>();
+        assert_eq!(a.stream(RngStream::Waves).random::<u64>(), b.stream(RngStream::Waves).random::<u64>());
+    }
+
+    #[test]
+    fn streams_differ() {
+        let mut rng = GameRng::new(42);
+        let world = rng.stream(RngStream::World).random::<u64>();
+        assert_ne!(world, rng.stream(RngStream::Waves).random::<u64>());
+    }
+
+    #[test]
+    fn reset_restarts_streams() {
+        let mut rng = GameRng::new(7);
+        let first = rng.stream(RngStream::Ai).random::<u64>();
+        rng.reset();
+        assert_eq!(first, rng.stream(RngStream::Ai).random::<u64>());
+    }
+
+    #[test]
+    fn sector_depends_only_on_seed_and_sector() {
+        let mut rng = GameRng::new(42);
+        let before = rng.sector(IVec3::new(3, -1, 7)).random::<u64>();
+        // Drawing from the world stream and visiting other sectors changes nothing
+        rng.stream(RngStream::World).random::<u64>();
+        rng.sector(IVec3::ZERO).random::<u64>();
+        assert_eq!(before, rng.sector(IVec3::new(3, -1, 7)).random::<u64>());
+        assert_eq!(before, GameRng::new(42).sector(IVec3::new(3, -1, 7)).random::<u64>());
+    }
+
+    #[test]
+    fn sectors_differ() {
+        let rng = GameRng::new(42);
+        let sectors = [IVec3::ZERO, IVec3::X, IVec3::Y, IVec3::Z, IVec3::NEG_X, IVec3::new(1, 1, 0), IVec3::new(0, 1, 1)];
+        let values: std::collections::HashSet<u64> = sectors.iter().map(|sector| rng.sector(*sector).random()).collect();
+        assert_eq!(values.len(), sectors.len());
+        assert_ne!(rng.sector(IVec3::X).random::<u64>(), GameRng::new(43).sector(IVec3::X).random::<u64>());
+    }
+
+    #[test]
+    fn random_directions_are_unit_length() {
+        let mut rng = GameRng::new(3);
+        for _ in 0..100 {
+            assert!(random_direction(rng.stream(RngStream::Effects)).is_normalized());
+        }
+    }
+}
@@ -9,19 +9,33 @@ use bevy::prelude::*;
 use bevy_rapier3d::geometry::Collider;
 use bevy_rapier3d::prelude::*;
 use rand::Rng;
+use std::f32::consts::PI;
 use crate::camera::MainCamera;
+use crate::game::{AppState, InGame};
+use crate::health::{EntityDestroyed, Health, VictimKind};
+use crate::rng::{random_direction, GameRng, RngStream};
+use crate::sector::SectorMember;
 use crate::spaceship::SpaceShip;
 
+/// Health of a rock of scale 1. Bigger rocks have proportionally more.
+const ROCK_HEALTH: f32 = 20.0;
+const ROCK_MIN_SCALE: f32 = 1.0;
+const ROCK_MAX_SCALE: f32 = 8.0;
+/// Rocks at least this big split into fragments when destroyed, smaller ones just break apart.
+const FRAGMENT_MIN_SCALE: f32 = 2.0;
+const SPAWN_MIN_DISTANCE: f32 = 400.0;
+const SPAWN_MAX_DISTANCE: f32 = 1000.0;
+const DESPAWN_DISTANCE: f32 = 1500.0;
+
 pub struct RockPlugin;
 
 #[derive(Component, Debug)]
 pub struct Rock;
 impl Plugin for RockPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (spawn_rocks))
-            .add_systems(PostStartup, spawn_rocks)
-            .add_systems(FixedUpdate, despawn_distant_rocks)
-            .insert_resource(IntervalTimer(Timer::from_seconds(3.0, TimerMode::Repeating)));
+        app.add_systems(Update, (spawn_rocks, fragment_rocks).chain().run_if(in_state(AppState::Playing)))
+            .add_systems(FixedUpdate, despawn_distant_rocks.run_if(in_state(AppState::Playing)))
+            .insert_resource(IntervalTimer(Timer::from_seconds(1.5, TimerMode::Repeating)));
     }
 }
 
@@ -33,52 +47,108 @@ fn spawn_rocks(mut commands: Commands,
                mut meshes: ResMut<Assets<Mesh>>,
                spaceship_query: Query<&Transform, With<SpaceShip>>,
                time: Res<Time>,
+               mut rng: ResMut<GameRng>,
                mut timer: ResMut<IntervalTimer>,) {
     if timer.0.tick(time.delta()).just_finished() {
         let spaceship_transform = spaceship_query.get_single().unwrap();
-        let scene_root = SceneRoot(space_kit.rock.clone());
-        commands.spawn(
-            (scene_root, random_rock_transform(&spaceship_transform.translation),
-             random_rock_velocity(),
-             RigidBody::Dynamic,
-             Collider::ball(1.),
-             GravityScale(0.),
-             Mesh3d(meshes.add(Cuboid::new(1., 1., 1.))), Rock));
+        let rng = rng.stream(RngStream::Rocks);
+        let scale = random_rock_scale(rng);
+        let transform = random_rock_transform(rng, &spaceship_transform.translation).with_scale(Vec3::splat(scale));
+        let velocity = random_rock_velocity(rng, scale);
+        spawn_rock(&mut commands, &space_kit, &mut meshes, transform, velocity, rock_health(scale));
+    }
+}
+
+pub fn rock_health(scale: f32) -> f32 {
+    ROCK_HEALTH * scale
+}
+
+/// Splits big rocks into smaller ones flying apart, keeping roughly the same total volume.
+fn fragment_rocks(
+    mut commands: Commands,
+    space_kit: Res<SpaceKit>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut rng: ResMut<GameRng>,
+    mut destroyed_events: EventReader<EntityDestroyed>,
+) {
+    let rng = rng.stream(RngStream::Rocks);
+    for destroyed in destroyed_events.read() {
+        // An unscaled rock has a radius of 1, so the size of a destroyed rock is its scale
+        let scale = destroyed.size;
+        if destroyed.kind != VictimKind::Rock || scale < FRAGMENT_MIN_SCALE {
+            continue;
+        }
+        let count = rng.random_range(2..=4);
+        let fragment_scale = (scale / (count as f32).cbrt()).max(ROCK_MIN_SCALE);
+        for _ in 0..count {
+            let direction = random_direction(rng);
+            let transform = Transform::from_translation(destroyed.position + direction * scale * 0.5)
+                .with_rotation(Quat::from_scaled_axis(random_direction(rng) * rng.random_range(0.0..PI)))
+                .with_scale(Vec3::splat(fragment_scale));
+            let velocity = Velocity {
+                linvel: direction * rng.random_range(10.0..40.0),
+                angvel: random_direction(rng) * rng.random_range(0.5..2.0),
+            };
+            spawn_rock(&mut commands, &space_kit, &mut meshes, transform, velocity, rock_health(fragment_scale));
+        }
     }
 }
 
+pub fn spawn_rock(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    transform: Transform,
+    velocity: Velocity,
+    health: f32,
+) -> Entity {
+    commands.spawn(
+        (SceneRoot(space_kit.rock.clone()), transform,
+         velocity,
+         RigidBody::Dynamic,
+         // Scaled along with the transform, so it always matches the model
+         Collider::ball(1.),
+         GravityScale(0.),
+         Mesh3d(meshes.add(Cuboid::new(1., 1., 1.))), Rock,
+         Health {
+             current: health,
+             max: rock_health(transform.scale.max_element()),
+         },
+         StateScoped(InGame))).id()
+}
+
+/// Culls the rocks spawned around the ship. Rocks that belong to a sector are unloaded with it.
 fn despawn_distant_rocks(mut commands: Commands,
           spaceship_query: Query<&Transform, With<SpaceShip>>,
-          rock_query: Query<(&Transform, Entity), With<Rock>>) {
+          rock_query: Query<(&Transform, Entity), (With<Rock>, Without<SectorMember>)>) {
     let spaceship_transform = spaceship_query.get_single().unwrap();
 
     for (rock_transform, rock_entity)  in rock_query.iter() {
         let rock_translation = rock_transform.translation;
         let distance = rock_translation.distance(spaceship_transform.translation);
-        if distance > 1500. {
+        if distance > DESPAWN_DISTANCE {
             commands.entity(rock_entity).despawn();
         }
     }
 }
 
-fn random_rock_velocity() -> Velocity{
-    let mut rng = rand::rng();
-    let x = rng.random_range(-100.0..100.0);
-    let y = rng.random_range(-100.0..100.0);
-    let z = rng.random_range(-100.0..100.0);
+/// Mostly small rocks with the occasional big one.
+pub fn random_rock_scale(rng: &mut impl Rng) -> f32 {
+    ROCK_MIN_SCALE + (ROCK_MAX_SCALE - ROCK_MIN_SCALE) * rng.random::<f32>().powi(2)
+}
+
+/// Drift and tumble, slower for bigger rocks.
+fn random_rock_velocity(rng: &mut impl Rng, scale: f32) -> Velocity {
+    let speed = rng.random_range(0.0..100.0) / scale.sqrt();
     Velocity {
-        linvel: Vec3::new(x, y, z),
-        angvel: Default::default(),
+        linvel: random_direction(rng) * speed,
+        angvel: random_direction(rng) * rng.random_range(0.1..1.5) / scale.sqrt(),
     }
 }
 
-fn random_rock_transform(spaceship_translation: &Vec3) -> Transform {
-    let mut rng = rand::rng();
-    let x = rng.random_range(10.0..500.0);
-    let y = rng.random_range(10.0..500.0);
-    let z = rng.random_range(10.0..500.0);
-    Transform {
-        translation: Vec3::new(spaceship_translation.x + x, spaceship_translation.y + y, spaceship_translation.z + z),
-        ..default()
-    }
-}
\ No newline at end of file
+/// A random point in a shell around the ship, far enough away to not pop in right in front of it.
+fn random_rock_transform(rng: &mut impl Rng, spaceship_translation: &Vec3) -> Transform {
+    let distance = rng.random_range(SPAWN_MIN_DISTANCE..SPAWN_MAX_DISTANCE);
+    Transform::from_translation(*spaceship_translation + random_direction(rng) * distance)
+        .with_rotation(Quat::from_scaled_axis(random_direction(rng) * rng.random_range(0.0..PI)))
+}
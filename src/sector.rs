@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use crate::asset::SpaceKit;
+use crate::game::{AppState, InGame};
+use crate::health::Health;
+use crate::planet::{spawn_moon, spawn_planet, Orbit, Planet, Spin};
+use crate::rng::GameRng;
+use crate::rock::{random_rock_scale, rock_health, spawn_rock, Rock};
+use crate::spaceship::SpaceShip;
+use crate::starsystem::{Body, StarSystem};
+
+/// Edge length of the cubes the world is divided into.
+const SECTOR_SIZE: f32 = 2000.0;
+/// Sectors up to this many sectors away from the player's, on every axis, are loaded.
+const LOAD_RADIUS: i32 = 1;
+/// Sectors are only unloaded beyond this, so flying back and forth over a border doesn't
+/// keep reloading them.
+const UNLOAD_RADIUS: i32 = 2;
+/// Points tried per sector when filling asteroid belts. Those inside a belt get a rock.
+const BELT_ROCK_ATTEMPTS: u32 = 60;
+const ROCK_CLUSTER_CHANCE: f64 = 0.3;
+const ROCK_CLUSTER_SPREAD: f32 = 150.0;
+const ROCK_DRIFT_SPEED: f32 = 5.0;
+
+/// Marks an entity as part of a sector's contents, unloaded and remembered with the sector.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SectorMember(pub IVec3);
+
+/// One thing in a sector, as generated or as it was when the sector was unloaded.
+#[derive(Debug, Clone)]
+enum SectorObject {
+    Body(Body),
+    Rock {
+        transform: Transform,
+        velocity: Velocity,
+        health: f32,
+    },
+}
+
+/// Which sectors are loaded, and what was left in the ones the player has visited before.
+/// A destroyed object is simply missing from its sector's memory, so it stays destroyed.
+#[derive(Resource, Debug, Default)]
+pub struct Sectors {
+    loaded: HashSet<IVec3>,
+    remembered: HashMap<IVec3, Vec<SectorObject>>,
+}
+
+impl Sectors {
+    pub fn loaded(&self) -> usize {
+        self.loaded.len()
+    }
+}
+
+fn sector_of(position: Vec3) -> IVec3 {
+    (position / SECTOR_SIZE).floor().as_ivec3()
+}
+
+pub struct SectorPlugin;
+
+impl Plugin for SectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Sectors>()
+            .add_systems(OnExit(InGame), forget_sectors)
+            .add_systems(Update, (unload_sectors, load_sectors).chain().run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn forget_sectors(mut sectors: ResMut<Sectors>) {
+    *sectors = Sectors::default();
+}
+
+/// Everything in a sector the first time it is visited.
+fn generate_sector(rng: &mut impl Rng, sector: IVec3, system: &StarSystem) -> Vec<SectorObject> {
+    let mut objects: Vec<SectorObject> = system.bodies
+        .iter()
+        .filter(|body| sector_of(body.anchor()) == sector)
+        .map(|body| SectorObject::Body(*body))
+        .collect();
+
+    for _ in 0..BELT_ROCK_ATTEMPTS {
+        let point = random_point(rng, sector);
+        if system.in_belt(point) {
+            objects.push(random_rock(rng, point));
+        }
+    }
+    if rng.random_bool(ROCK_CLUSTER_CHANCE) {
+        let center = random_point(rng, sector);
+        for _ in 0..rng.random_range(5..15) {
+            let offset = Vec3::new(
+                rng.random_range(-ROCK_CLUSTER_SPREAD..ROCK_CLUSTER_SPREAD),
+                rng.random_range(-ROCK_CLUSTER_SPREAD..ROCK_CLUSTER_SPREAD),
+                rng.random_range(-ROCK_CLUSTER_SPREAD..ROCK_CLUSTER_SPREAD),
+            );
+            objects.push(random_rock(rng, center + offset));
+        }
+    }
+    objects
+}
+
+/// A slowly drifting and tumbling rock at `position`.
+fn random_rock(rng: &mut impl Rng, position: Vec3) -> SectorObject {
+    let drift = Vec3::new(
+        rng.random_range(-ROCK_DRIFT_SPEED..ROCK_DRIFT_SPEED),
+        rng.random_range(-ROCK_DRIFT_SPEED..ROCK_DRIFT_SPEED),
+        rng.random_range(-ROCK_DRIFT_SPEED..ROCK_DRIFT_SPEED),
+    );
+    let scale = random_rock_scale(rng);
+    SectorObject::Rock {
+        transform: Transform::from_translation(position).with_scale(Vec3::splat(scale)),
+        velocity: Velocity {
+            linvel: drift / scale.sqrt(),
+            angvel: Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+            ) / scale.sqrt(),
+        },
+        health: rock_health(scale),
+    }
+}
+
+fn random_point(rng: &mut impl Rng, sector: IVec3) -> Vec3 {
+    let offset = Vec3::new(rng.random::<f32>(), rng.random::<f32>(), rng.random::<f32>());
+    (sector.as_vec3() + offset) * SECTOR_SIZE
+}
+
+fn load_sectors(
+    mut commands: Commands,
+    space_kit: Res<SpaceKit>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    rng: Res<GameRng>,
+    system: Res<StarSystem>,
+    mut sectors: ResMut<Sectors>,
+    spaceship_query: Query<&Transform, With<SpaceShip>>,
+) {
+    let Ok(spaceship_transform) = spaceship_query.get_single() else {
+        return;
+    };
+    let center = sector_of(spaceship_transform.translation);
+    let range = -LOAD_RADIUS..=LOAD_RADIUS;
+    for x in range.clone() {
+        for y in range.clone() {
+            for z in range.clone() {
+                let sector = center + IVec3::new(x, y, z);
+                if !sectors.loaded.insert(sector) {
+                    continue;
+                }
+                let objects = sectors.remembered
+                    .remove(&sector)
+                    .unwrap_or_else(|| generate_sector(&mut rng.sector(sector), sector, &system));
+                for object in objects {
+                    let entity = match object {
+                        SectorObject::Body(Body::Planet { model, transform, spin }) => {
+                            let planet = spawn_planet(&mut commands, &space_kit, &mut meshes, model, transform);
+                            commands.entity(planet).insert(spin);
+                            planet
+                        }
+                        SectorObject::Body(Body::Moon { model, scale, orbit }) => {
+                            spawn_moon(&mut commands, &space_kit, &mut meshes, model, scale, orbit)
+                        }
+                        SectorObject::Rock { transform, velocity, health } => {
+                            spawn_rock(&mut commands, &space_kit, &mut meshes, transform, velocity, health)
+                        }
+                    };
+                    commands.entity(entity).insert(SectorMember(sector));
+                }
+            }
+        }
+    }
+}
+
+/// Despawns sectors the player has left behind, remembering what was still in them.
+fn unload_sectors(
+    mut commands: Commands,
+    mut sectors: ResMut<Sectors>,
+    spaceship_query: Query<&Transform, With<SpaceShip>>,
+    member_query: Query<(
+        Entity,
+        &SectorMember,
+        &Transform,
+        Option<&Velocity>,
+        Option<&Health>,
+        Has<Rock>,
+        Option<&Planet>,
+        Option<&Orbit>,
+        Option<&Spin>,
+    )>,
+) {
+    let Ok(spaceship_transform) = spaceship_query.get_single() else {
+        return;
+    };
+    let center = sector_of(spaceship_transform.translation);
+    let distant: Vec<IVec3> = sectors.loaded
+        .iter()
+        .filter(|sector| (**sector - center).abs().max_element() > UNLOAD_RADIUS)
+        .copied()
+        .collect();
+    if distant.is_empty() {
+        return;
+    }
+
+    for sector in &distant {
+        sectors.loaded.remove(sector);
+        sectors.remembered.insert(*sector, Vec::new());
+    }
+    for (entity, member, transform, velocity, health, is_rock, planet, orbit, spin) in member_query.iter() {
+        if !distant.contains(&member.0) {
+            continue;
+        }
+        // Moons keep their place in the orbit and planets their turn
+        let object = match (planet, orbit) {
+            (Some(planet), Some(orbit)) => Some(SectorObject::Body(Body::Moon {
+                model: planet.model,
+                scale: transform.scale.max_element(),
+                orbit: *orbit,
+            })),
+            (Some(planet), None) => Some(SectorObject::Body(Body::Planet {
+                model: planet.model,
+                transform: *transform,
+                spin: spin.copied().unwrap_or(Spin(Vec3::ZERO)),
+            })),
+            (None, _) => is_rock.then(|| SectorObject::Rock {
+                transform: *transform,
+                velocity: velocity.copied().unwrap_or_default(),
+                health: health.map_or(rock_health(transform.scale.max_element()), |health| health.current),
+            }),
+        };
+        if let Some(remembered) = sectors.remembered.get_mut(&member.0) {
+            remembered.extend(object);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_load_with_their_sector() {
+        let planet = Body::Planet {
+            model: 0,
+            transform: Transform::from_xyz(5000.0, 100.0, -3000.0),
+            spin: Spin(Vec3::ZERO),
+        };
+        let orbit = Orbit {
+            center: planet.anchor(),
+            radius: 1500.0,
+            angle: 0.0,
+            angular_speed: 0.1,
+            tilt: Quat::IDENTITY,
+        };
+        let moon = Body::Moon {
+            model: 1,
+            scale: 20.0,
+            orbit,
+        };
+        let system = StarSystem {
+            bodies: vec![planet, moon],
+            ..default()
+        };
+        let rng = GameRng::new(1);
+        let bodies = |sector| {
+            generate_sector(&mut rng.sector(sector), sector, &system)
+                .iter()
+                .filter(|object| matches!(object, SectorObject::Body(_)))
+                .count()
+        };
+        assert_eq!(bodies(sector_of(planet.anchor())), 2);
+        // The moon is in the next sector over, but it loads with its planet
+        assert_ne!(sector_of(orbit.position()), sector_of(planet.anchor()));
+        assert_eq!(bodies(sector_of(orbit.position())), 0);
+        assert_eq!(bodies(IVec3::ZERO), 0);
+    }
+}
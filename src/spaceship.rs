@@ -1,24 +1,35 @@
 use crate::asset::SpaceKit;
-use crate::bullet::spawn_bullet;
+use crate::bullet::{solid_ray_filter, BulletAssets, BulletPool, BulletSet};
 use crate::camera::MainCamera;
+use crate::faction::Faction;
+use crate::game::{AppState, InGame};
+use crate::health::{Health, Shield};
+use crate::input::{Action, ActionState, InputSet, PilotActions};
+use crate::laser::LaserEnergy;
+use crate::missile::MissileLauncher;
+use crate::rng::{GameRng, RngStream};
+use crate::weapon::{fire_weapon, WeaponDefinitions, WeaponSlot};
 use bevy::app::{App, Plugin};
 use bevy::prelude::*;
 use bevy_rapier3d::geometry::Collider;
-use bevy_rapier3d::math::Real;
 use bevy_rapier3d::prelude::*;
 
 use bevy::window::PrimaryWindow;
-use rand::random_range;
 use std::time::Duration;
 
+const HULL_HEALTH: f32 = 100.0;
+const SHIELD_CAPACITY: f32 = 50.0;
+const SHIELD_REGEN_RATE: f32 = 10.0;
+const SHIELD_REGEN_DELAY: Duration = Duration::from_secs(3);
+const BOOST_MULTIPLIER: f32 = 2.0;
+const AIM_RANGE: f32 = 3000.0;
+const AIM_FALLBACK_DISTANCE: f32 = 500.0;
+
 pub struct SpaceshipPlugin;
 
 #[derive(Component, Debug)]
 pub struct SpaceShip;
 
-#[derive(Component)]
-struct LaserBeam;
-
 #[derive(Component)]
 struct ShipTrail;
 
@@ -27,13 +38,25 @@ pub struct SpaceshipThrusted {
     pub ship_transform: Transform,
 }
 
-#[derive(Resource, Debug)]
-pub struct FireRate(Timer);
+/// Where the ship's guns point: straight ahead, or converging on whatever is under the
+/// mouse cursor.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AimMode {
+    #[default]
+    Forward,
+    Mouse,
+}
 impl Plugin for SpaceshipPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(FireRate(Timer::new(Duration::from_millis(100), TimerMode::Repeating)))
-            .add_systems(Startup, (spawn_space_ship, spawn_star_streaks))
-            .add_systems(Update, (control_spaceship, fire_bullet))
+        app.init_resource::<AimMode>()
+            .add_systems(OnEnter(InGame), spawn_space_ship)
+            .add_systems(Update, (
+                (toggle_aim_mode, aim_at_cursor)
+                    .chain()
+                    .after(InputSet::Devices)
+                    .before(InputSet::Override),
+                (control_spaceship, (switch_weapon, fire_bullet).chain().in_set(BulletSet::Fire)).after(InputSet::Override),
+            ).run_if(in_state(AppState::Playing)))
             .add_event::<SpaceshipThrusted>();
     }
 }
@@ -41,6 +64,7 @@ impl Plugin for SpaceshipPlugin {
 fn spawn_space_ship(
     mut commands: Commands,
     space_kit: Res<SpaceKit>,
+    weapons: Res<WeaponDefinitions>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>
 ) {
@@ -67,7 +91,16 @@ fn spawn_space_ship(
         GravityScale(0.),
         Mesh3d(meshes.add(Capsule3d::default())),
         SpaceShip,
-    )).with_children(|parent| {
+        Faction::Player,
+        Health::new(HULL_HEALTH),
+        Shield::new(SHIELD_CAPACITY, SHIELD_REGEN_RATE, SHIELD_REGEN_DELAY),
+        (
+            LaserEnergy::default(),
+            WeaponSlot::new(weapons.player_loadout.clone()),
+            MissileLauncher::player(),
+        ),
+        StateScoped(InGame),
+    )).insert(ActiveEvents::COLLISION_EVENTS).with_children(|parent| {
         parent.spawn((
             Mesh3d(meshes.add(Sphere::new(0.5).mesh().ico(5).unwrap())),
             MeshMaterial3d(exhaust_material.clone()),
@@ -91,7 +124,7 @@ fn spawn_space_ship(
 }
 
 fn control_spaceship(
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Res<PilotActions>,
     mut ship_query: Query<(&Transform, &mut ExternalForce), With<SpaceShip>>,
     mut trail_query: Query<(&mut Visibility), With<ShipTrail>>,
     mut thrusted_events: EventWriter<SpaceshipThrusted>,
@@ -102,233 +135,120 @@ fn control_spaceship(
     let right = Vec3::from(transform.right());
     let up = Vec3::from(transform.up());
 
-    let thrust_force = 600.0;
+    let thrust_force = if actions.boost { 600.0 * BOOST_MULTIPLIER } else { 600.0 };
     let rotation_torque = 200.0;
 
-    // Movement (W/S)
-    let mut linear_force = Vec3::ZERO;
+    // Movement (the ship model faces +Z, so thrusting pushes against `forward`)
+    let linear_force = -forward * actions.thrust;
     let mut trail_visibility = Visibility::Hidden;
-    if keyboard.pressed(KeyCode::KeyW) {
-        linear_force -= forward;
+    if actions.thrust > 0.0 {
         trail_visibility = Visibility::Visible;
         thrusted_events.send(SpaceshipThrusted {
             ship_transform: transform.clone(),
         });
     }
-    if keyboard.pressed(KeyCode::KeyS) {
-        linear_force += forward;
-    }
 
-    // Pitch (Up/Down)
-    let mut angular_torque = Vec3::ZERO;
-    if keyboard.pressed(KeyCode::ArrowUp) {
-        angular_torque += right; // pitch up
-    }
-    if keyboard.pressed(KeyCode::ArrowDown) {
-        angular_torque -= right; // pitch down
-    }
-
-    // Yaw (A/D)
-    if keyboard.pressed(KeyCode::KeyA) {
-        angular_torque += up; // yaw left
-    }
-    if keyboard.pressed(KeyCode::KeyD) {
-        angular_torque -= up; // yaw right
-    }
-
-    // Roll (Q/E)
-    if keyboard.pressed(KeyCode::KeyQ) {
-        angular_torque += forward; // roll left
-    }
-    if keyboard.pressed(KeyCode::KeyE) {
-        angular_torque -= forward; // roll right
-    }
+    // Pitch, yaw and roll
+    let angular_torque = right * actions.pitch + up * actions.yaw + forward * actions.roll;
 
     for mut visibility in trail_query.iter_mut() {
         *visibility = trail_visibility;
     }
 
-    force.force = linear_force.normalize_or_zero() * thrust_force;
-    force.torque = angular_torque.normalize_or_zero() * rotation_torque;
+    // Analog input gives partial thrust and torque, only the combined magnitude is capped
+    force.force = linear_force.clamp_length_max(1.0) * thrust_force;
+    force.torque = angular_torque.clamp_length_max(1.0) * rotation_torque;
 
     //println!("ship translation: {:?}", transform.translation);
 }
 
-fn fire_bullet(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    time: Res<Time>,
-    mut fire_rate: ResMut<FireRate>,
-    spaceship_query: Query<&Transform, (With<SpaceShip>, Without<MainCamera>)>,
-    input: Res<ButtonInput<KeyCode>>,
+fn toggle_aim_mode(action_state: Res<ActionState>, mut aim_mode: ResMut<AimMode>) {
+    if action_state.just_pressed(Action::ToggleAim) {
+        *aim_mode = match *aim_mode {
+            AimMode::Forward => AimMode::Mouse,
+            AimMode::Mouse => AimMode::Forward,
+        };
+    }
+}
+
+fn aim_at_cursor(
+    aim_mode: Res<AimMode>,
+    mut actions: ResMut<PilotActions>,
     windows: Query<&Window, With<PrimaryWindow>>,
     camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) {
-    if fire_rate.0.tick(time.delta()).just_finished() {
-        if input.pressed(KeyCode::Space) {
-            let spaceship_transform = spaceship_query.get_single().unwrap();
-            // Forward direction in world space
-            let forward = spaceship_transform.forward(); // Vec3
-            // Bullet spawn position slightly in front of the ship
-            let translation = spaceship_transform.translation;
-            let spawn_position = translation + forward * -15.0;
-            let spawn_position_x = translation + spaceship_transform.left() * 5.;
-            let spawn_position_y = translation + spaceship_transform.right() * 5.;
-
-            // Bullet speed
-            let speed = 700.0;
-            let forward = spaceship_transform.forward().normalize();
-            for position in vec![spawn_position, spawn_position_x, spawn_position_y] {
-                spawn_bullet(
-                    &mut commands,
-                    &mut meshes,
-                    &mut materials,
-                    Velocity {
-                        linvel: -forward * speed,
-                        ..default()
-                    },
-                    Transform::from_translation(position),
-                );
-            }
-            /*if let Some(ray) = get_mouse_world_ray(windows, camera_q) {
-                spawn_bullet(
-                    &mut commands,
-                    &mut meshes,
-                    &mut materials,
-                    Velocity {
-                        linvel: ray.direction * speed,
-                        ..default()
-                    },
-                    Transform::from_translation(spawn_position),
-                );
-            }*/
-        }
+    actions.aim = match *aim_mode {
+        AimMode::Forward => None,
+        AimMode::Mouse => get_mouse_world_ray(&windows, &camera_q),
     };
 }
 
-fn spawn_laser_beam(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgba(0.0, 1.0, 0.0, 0.5),
-        emissive: LinearRgba::from(Color::srgb(0.0, 3.0, 0.0)),
-        unlit: true,
-        alpha_mode: AlphaMode::Add,
-        ..default()
-    });
-
-    commands.spawn((
-        Mesh3d(meshes.add(Cylinder::new(0.05, 1.0))),
-        MeshMaterial3d(material),
-        Transform::from_xyz(0.0, 0.0, 5.0),
-        LaserBeam,
-    ));
-}
-
-fn update_laser_beam(
-    ship_query: Query<(Entity, &Transform), (With<SpaceShip>, Without<LaserBeam>)>,
-    mut beam_query: Query<&mut Transform, With<LaserBeam>>,
-    rapier_context: ReadRapierContext,
-    mut gizmos: Gizmos,
-) {
-    let (ship_entity, ship_transform) = ship_query.single();
-    let dir = -ship_transform.forward().as_vec3(); // ship looks along -Z
-    let origin = ship_transform.translation;
-    let max_distance: Real = 5000.0;
-
-    let end = if let Some((_e, toi)) = rapier_context.single().cast_ray(
-        origin,
-        dir,
-        max_distance,
-        true,
-        QueryFilter::default().exclude_collider(ship_entity),
-    ) {
-        println!("intersection: {:?}", _e);
-        origin + dir * toi as f32
-    } else {
-        origin + dir * max_distance
-    };
-
-    let distance = origin.distance(end);
-    let mut beam = beam_query.single_mut();
-
-    let mid_point = (origin + end) / 2.0;
-    beam.translation = origin + dir * 5.0;               // center the beam between start and end
-    beam.look_to(end, Vec3::Y);   // rotate so Y axis points in the direction
-    beam.scale = Vec3::new(0.5, 0.5, distance);
-
-    //gizmos.ray(origin, end, BLUE);
+/// The point the guns converge on: the first collider along the aim ray, or a point at a
+/// fixed distance when the ray hits nothing.
+fn aim_point(
+    ray: Ray3d,
+    ship_entity: Entity,
+    rapier_context: &ReadRapierContext,
+) -> Vec3 {
+    match rapier_context.single().cast_ray(ray.origin, *ray.direction, AIM_RANGE, true, solid_ray_filter(ship_entity)) {
+        Some((_, toi)) => ray.get_point(toi),
+        None => ray.get_point(AIM_FALLBACK_DISTANCE),
+    }
 }
 
-fn spawn_laser(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgba(0.0, 1.0, 0.0, 0.5),
-        emissive: LinearRgba::from(Color::srgb(0.0, 3.0, 0.0)),
-        unlit: true,
-        alpha_mode: AlphaMode::Add,
-        ..default()
-    });
-
-    commands.spawn((
-        Mesh3d(meshes.add(Cylinder::new(1., 1.0))),
-        MeshMaterial3d(material),
-        Transform {
-            translation: Vec3::new(0.0, 0.3, 5.0),
-            rotation: Quat::from_axis_angle(Vec3::Z, 0.0),
-            scale: Vec3::new(1.0, 1.0, 500.0),
-        }
-    ));
+fn switch_weapon(actions: Res<PilotActions>, mut slot_query: Query<&mut WeaponSlot, With<SpaceShip>>) {
+    let step = actions.next_weapon as i32 - actions.previous_weapon as i32;
+    if step == 0 {
+        return;
+    }
+    if let Ok(mut slot) = slot_query.get_single_mut() {
+        slot.cycle(step);
+    }
 }
 
-#[derive(Component)]
-struct StarStreak;
-fn spawn_star_streaks(
+fn fire_bullet(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut pool: ResMut<BulletPool>,
+    bullet_assets: Res<BulletAssets>,
+    time: Res<Time>,
+    weapons: Res<WeaponDefinitions>,
+    mut rng: ResMut<GameRng>,
+    mut spaceship_query: Query<(Entity, &Transform, &mut WeaponSlot), (With<SpaceShip>, Without<MainCamera>)>,
+    actions: Res<PilotActions>,
+    rapier_context: ReadRapierContext,
 ) {
-    let streak_material = materials.add(StandardMaterial {
-        base_color: Color::srgba(1.0, 1.0, 1.0, 0.3),
-        emissive: Color::WHITE.into(),
-        alpha_mode: AlphaMode::Add,
-        unlit: true,
-        ..default()
-    });
-
-    let quad = meshes.add(Cuboid::new(0.2, 0.2, 0.2));
-
-    for _ in 0..10000 {
-        let x = random_range(-1000..1000) as f32;
-        let y = random_range(-1000..1000) as f32;
-        let z = random_range(-1000..1000) as f32;
-        commands.spawn((
-            Mesh3d(quad.clone()),
-            MeshMaterial3d(streak_material.clone()),
-            Transform {
-                translation: Vec3::new(x, y, z),
-                ..default()
-            },
-            StarStreak,
-        ));
+    let Ok((spaceship_entity, spaceship_transform, mut slot)) = spaceship_query.get_single_mut() else {
+        return;
+    };
+    let Some((weapon, visual)) = slot.active_weapon().and_then(|name| weapons.get(name).zip(bullet_assets.get(name))) else {
+        return;
+    };
+    if slot.fire(time.delta_secs(), actions.fire, weapon) {
+        let target = actions.aim.map(|ray| aim_point(ray, spaceship_entity, &rapier_context));
+        // The ship model's nose is its local +Z axis, which is what weapons fire along
+        fire_weapon(
+            &mut commands,
+            &mut pool,
+            visual,
+            rng.stream(RngStream::PlayerWeapons),
+            weapon,
+            spaceship_entity,
+            Faction::Player,
+            spaceship_transform.translation,
+            spaceship_transform.rotation,
+            target,
+        );
     }
 }
 
 fn get_mouse_world_ray(
-    windows: Query<&Window, With<PrimaryWindow>>,
-    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    camera_q: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) -> Option<Ray3d> {
-    let (camera, cam_transform) = camera_q.single();
-    let window = windows.single();
+    // There is no window or camera when running headless
+    let (camera, cam_transform) = camera_q.get_single().ok()?;
+    let window = windows.get_single().ok()?;
 
     let cursor_pos = window.cursor_position()?;
-    let ray = camera.viewport_to_world(cam_transform, cursor_pos).unwrap();
-
-    Some(ray)
+    camera.viewport_to_world(cam_transform, cursor_pos).ok()
 }
\ No newline at end of file
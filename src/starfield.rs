@@ -0,0 +1,132 @@
+use bevy::app::{App, Plugin};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::view::NoFrustumCulling;
+use rand::Rng;
+use crate::camera::MainCamera;
+use crate::game::InGame;
+use crate::rng::{GameRng, RngStream};
+
+const STAR_COUNT: usize = 10000;
+/// Edge length of the cube of stars around the camera. Stars leaving one side reappear
+/// on the opposite one.
+const FIELD_SIZE: f32 = 2000.0;
+/// Seconds of camera movement each streak covers.
+const STREAK_TIME: f32 = 0.05;
+/// Length of a star when the camera is not moving, so it still shows up as a dot.
+const MIN_STREAK_LENGTH: f32 = 0.4;
+/// How quickly the streak length follows changes in speed, per second.
+const STREAK_RESPONSE: f32 = 8.0;
+
+/// Every star is one line in a single mesh, rewritten each frame around the camera.
+#[derive(Component, Debug)]
+struct Starfield {
+    stars: Vec<Vec3>,
+    mesh: Handle<Mesh>,
+    previous_camera: Option<Vec3>,
+    velocity: Vec3,
+}
+
+pub struct StarfieldPlugin;
+
+impl Plugin for StarfieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(InGame), spawn_starfield)
+            .add_systems(
+                PostUpdate,
+                update_starfield
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+fn spawn_starfield(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgba(1.0, 1.0, 1.0, 0.3),
+        emissive: Color::WHITE.into(),
+        alpha_mode: AlphaMode::Add,
+        unlit: true,
+        ..default()
+    });
+
+    let rng = rng.stream(RngStream::Stars);
+    let half = FIELD_SIZE / 2.0;
+    let stars: Vec<Vec3> = (0..STAR_COUNT)
+        .map(|_| Vec3::new(
+            rng.random_range(-half..half),
+            rng.random_range(-half..half),
+            rng.random_range(-half..half),
+        ))
+        .collect();
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![Vec3::ZERO; STAR_COUNT * 2]);
+    let mesh = meshes.add(mesh);
+
+    commands.spawn((
+        Mesh3d(mesh.clone()),
+        MeshMaterial3d(material),
+        Transform::default(),
+        // Vertices move every frame, so the bounds computed at spawn would go stale
+        NoFrustumCulling,
+        NotShadowCaster,
+        Starfield {
+            stars,
+            mesh,
+            previous_camera: None,
+            velocity: Vec3::ZERO,
+        },
+        StateScoped(InGame),
+    ));
+}
+
+/// Centres the starfield on the camera, wraps every star into the cube around it and
+/// stretches it along the camera's velocity.
+fn update_starfield(
+    time: Res<Time>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    camera_query: Query<&Transform, (With<MainCamera>, Without<Starfield>)>,
+    mut starfield_query: Query<(&mut Transform, &mut Starfield)>,
+) {
+    let (Ok(camera_transform), Ok((mut transform, mut starfield))) =
+        (camera_query.get_single(), starfield_query.get_single_mut()) else {
+        return;
+    };
+    let camera = camera_transform.translation;
+    let delta = time.delta_secs();
+    if let Some(previous) = starfield.previous_camera {
+        if delta > 0.0 {
+            let velocity = (camera - previous) / delta;
+            starfield.velocity = starfield.velocity.lerp(velocity, (STREAK_RESPONSE * delta).min(1.0));
+        }
+    }
+    starfield.previous_camera = Some(camera);
+
+    let streak = starfield.velocity * STREAK_TIME;
+    let streak = if streak.length() < MIN_STREAK_LENGTH {
+        camera_transform.up() * MIN_STREAK_LENGTH
+    } else {
+        streak
+    };
+
+    let half = Vec3::splat(FIELD_SIZE / 2.0);
+    let positions: Vec<Vec3> = starfield.stars
+        .iter()
+        .flat_map(|star| {
+            let local = (*star - camera + half).rem_euclid(Vec3::splat(FIELD_SIZE)) - half;
+            [local, local + streak]
+        })
+        .collect();
+    if let Some(mesh) = meshes.get_mut(&starfield.mesh) {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    }
+    transform.translation = camera;
+}
@@ -0,0 +1,235 @@
+use std::f32::consts::TAU;
+use bevy::app::{App, Plugin};
+use bevy::pbr::CascadeShadowConfigBuilder;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use crate::asset::SpaceKit;
+use crate::camera::MainCamera;
+use crate::game::InGame;
+use crate::planet::{GravityWell, Orbit, Spin};
+use crate::rng::{GameRng, RngStream};
+
+const STAR_RADIUS: f32 = 1500.0;
+const STAR_SURFACE_GRAVITY: f32 = 60.0;
+const FIRST_ORBIT: f32 = 6000.0;
+/// Range of distances between neighbouring orbits. Wide enough that the reach of two
+/// neighbouring planets' gravity never overlaps.
+const ORBIT_SPACING: (f32, f32) = (8000.0, 12000.0);
+const ORBITS: (usize, usize) = (4, 8);
+const BELT_CHANCE: f64 = 0.25;
+const BELT_WIDTH: f32 = 2500.0;
+const BELT_THICKNESS: f32 = 600.0;
+/// The star's pull ends well inside the innermost orbit. Belt rocks only drift, so a pull
+/// reaching them would drag the belts into the star over a round.
+const STAR_GRAVITY_REACH: f32 = FIRST_ORBIT - BELT_WIDTH;
+/// Range of planet scales. Each planet model gets its own size. Small enough that neither
+/// the pull nor the moons of a planet reach halfway to the next orbit.
+const PLANET_SCALES: (f32, f32) = (100.0, 250.0);
+/// Size of a moon relative to what its model would be as a planet.
+const MOON_SCALE: f32 = 0.2;
+/// Radians per second planets spin at, at most.
+const MAX_SPIN: f32 = 0.05;
+const MAX_MOONS: u32 = 3;
+const MOON_CHANCE: f64 = 0.5;
+/// Largest tilt of an orbit out of the system's plane, in radians.
+const MAX_TILT: f32 = 0.15;
+
+/// A planet or moon as it was generated. Bodies are left to the sector streaming to spawn,
+/// so only those near the player exist as entities.
+#[derive(Debug, Clone, Copy)]
+pub enum Body {
+    Planet {
+        model: usize,
+        transform: Transform,
+        spin: Spin,
+    },
+    Moon {
+        model: usize,
+        scale: f32,
+        orbit: Orbit,
+    },
+}
+
+impl Body {
+    /// Where the body belongs. A moon belongs to its planet, so the two load together.
+    pub fn anchor(&self) -> Vec3 {
+        match self {
+            Body::Planet { transform, .. } => transform.translation,
+            Body::Moon { orbit, .. } => orbit.center,
+        }
+    }
+}
+
+/// A ring of asteroids around the star, in the system's plane.
+#[derive(Debug, Clone, Copy)]
+pub struct AsteroidBelt {
+    pub radius: f32,
+    pub width: f32,
+    pub thickness: f32,
+}
+
+/// Layout of the star system the round is played in, generated from the world seed.
+#[derive(Resource, Debug, Default)]
+pub struct StarSystem {
+    pub star: Vec3,
+    /// Turns the XZ plane into the plane planets orbit in.
+    pub plane: Quat,
+    /// Centre and radius of every planet, moons not included.
+    pub planets: Vec<(Vec3, f32)>,
+    pub bodies: Vec<Body>,
+    pub belts: Vec<AsteroidBelt>,
+}
+
+impl StarSystem {
+    /// Whether `point` lies inside one of the asteroid belts.
+    pub fn in_belt(&self, point: Vec3) -> bool {
+        let local = self.plane.inverse() * (point - self.star);
+        let distance = Vec2::new(local.x, local.z).length();
+        self.belts.iter().any(|belt| {
+            (distance - belt.radius).abs() < belt.width / 2.0 && local.y.abs() < belt.thickness / 2.0
+        })
+    }
+}
+
+/// The star's light. It is a directional light turned every frame to shine from the star
+/// towards the camera, so shadows are sharp wherever the player is.
+#[derive(Component, Debug)]
+struct StarLight;
+
+pub struct StarSystemPlugin;
+
+impl Plugin for StarSystemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StarSystem>()
+            .add_systems(OnEnter(InGame), spawn_star_system)
+            .add_systems(Update, aim_star_light.run_if(in_state(InGame)));
+    }
+}
+
+pub fn spawn_star_system(
+    mut commands: Commands,
+    space_kit: Res<SpaceKit>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream(RngStream::World);
+    let plane = Quat::from_euler(EulerRot::XYZ, rng.random_range(-0.5..0.5), rng.random_range(0.0..TAU), rng.random_range(-0.5..0.5));
+
+    let mut radii = vec![FIRST_ORBIT];
+    for _ in 1..rng.random_range(ORBITS.0..=ORBITS.1) {
+        let last = radii[radii.len() - 1];
+        radii.push(last + rng.random_range(ORBIT_SPACING.0..ORBIT_SPACING.1));
+    }
+    // The player starts halfway between two orbits, clear of both planets' pull
+    let home = rng.random_range(0..radii.len() - 1);
+    let home_radius = (radii[home] + radii[home + 1]) / 2.0;
+    let star = -(plane * Quat::from_rotation_y(rng.random_range(0.0..TAU)) * Vec3::X) * home_radius;
+
+    let mut models: Vec<usize> = (0..space_kit.planets.len()).collect();
+    models.shuffle(rng);
+    let scales: Vec<f32> = models.iter().map(|_| rng.random_range(PLANET_SCALES.0..PLANET_SCALES.1)).collect();
+    let mut models = models.into_iter().zip(scales).cycle();
+
+    let mut belts = Vec::new();
+    let mut planets = Vec::new();
+    let mut bodies = Vec::new();
+    for radius in radii {
+        if rng.random_bool(BELT_CHANCE) {
+            belts.push(AsteroidBelt {
+                radius,
+                width: BELT_WIDTH,
+                thickness: BELT_THICKNESS,
+            });
+            continue;
+        }
+        let Some((model, scale)) = models.next() else {
+            break;
+        };
+        let tilt = plane * Quat::from_rotation_x(rng.random_range(-MAX_TILT..MAX_TILT));
+        // Planets stay put rather than orbit the star: stations hold position above them, and
+        // an orbit this far out would be faster than the ship can fly
+        let center = star + tilt * Quat::from_rotation_y(rng.random_range(0.0..TAU)) * Vec3::X * radius;
+        bodies.push(Body::Planet {
+            model,
+            transform: Transform::from_translation(center).with_scale(Vec3::splat(scale)),
+            spin: Spin(tilt * Vec3::Y * rng.random_range(-MAX_SPIN..MAX_SPIN)),
+        });
+
+        let well = GravityWell::for_planet(scale);
+        planets.push((center, well.surface));
+        for moon in 0..MAX_MOONS {
+            if !rng.random_bool(MOON_CHANCE) {
+                break;
+            }
+            let Some((moon_model, moon_scale)) = models.next() else {
+                break;
+            };
+            // Moons get further apart so they never run into each other
+            let moon_radius = well.surface * (2.5 + 1.5 * moon as f32 + rng.random_range(0.0..0.5));
+            bodies.push(Body::Moon {
+                model: moon_model,
+                scale: moon_scale * MOON_SCALE,
+                orbit: Orbit {
+                    center,
+                    radius: moon_radius,
+                    angle: rng.random_range(0.0..TAU),
+                    angular_speed: well.orbital_speed(moon_radius) / moon_radius,
+                    tilt: tilt * Quat::from_rotation_x(rng.random_range(-0.5..0.5)),
+                },
+            });
+        }
+    }
+
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(STAR_RADIUS).mesh().ico(5).unwrap())),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.9, 0.6),
+            emissive: LinearRgba::new(20.0, 15.0, 6.0, 1.0),
+            unlit: true,
+            ..default()
+        })),
+        Transform::from_translation(star),
+        RigidBody::Fixed,
+        Collider::ball(STAR_RADIUS),
+        GravityWell {
+            strength: STAR_SURFACE_GRAVITY * STAR_RADIUS * STAR_RADIUS,
+            surface: STAR_RADIUS,
+            reach: STAR_GRAVITY_REACH,
+        },
+        StateScoped(InGame),
+    ));
+    commands.spawn((
+        DirectionalLight {
+            illuminance: light_consts::lux::AMBIENT_DAYLIGHT,
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::from_translation(star),
+        CascadeShadowConfigBuilder {
+            first_cascade_far_bound: 100.0,
+            maximum_distance: 2000.0,
+            ..default()
+        }
+        .build(),
+        StarLight,
+        StateScoped(InGame),
+    ));
+
+    info!("Star system: {} planets, {} asteroid belts", planets.len(), belts.len());
+    commands.insert_resource(StarSystem { star, plane, planets, bodies, belts });
+}
+
+fn aim_star_light(
+    system: Res<StarSystem>,
+    camera_query: Query<&Transform, (With<MainCamera>, Without<StarLight>)>,
+    mut light_query: Query<&mut Transform, With<StarLight>>,
+) {
+    let (Ok(camera_transform), Ok(mut light_transform)) = (camera_query.get_single(), light_query.get_single_mut()) else {
+        return;
+    };
+    let outward = (camera_transform.translation - system.star).normalize_or(Vec3::NEG_Z);
+    *light_transform = Transform::from_translation(system.star).looking_to(outward, Vec3::Y);
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::path::Path;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::asset::{load_ron, SpaceKit, STATION_PARTS};
+use crate::bullet::{BulletAssets, BulletPool, BulletSet};
+use crate::faction::Faction;
+use crate::game::{AppState, InGame};
+use crate::health::{Destroyed, Health};
+use crate::rng::{random_direction, GameRng, RngStream};
+use crate::spaceship::SpaceShip;
+use crate::starsystem::{spawn_star_system, StarSystem};
+use crate::weapon::{fire_weapon, WeaponDefinitions, WeaponSlot};
+
+const STATIONS_PATH: &str = "assets/stations.ron";
+/// How far from where the player starts the home hub is.
+const HUB_DISTANCE: f32 = 600.0;
+const HUB_REPAIR_RANGE: f32 = 300.0;
+/// Hull points per second a friendly hub repairs.
+const HUB_REPAIR_RATE: f32 = 10.0;
+const BASE_CHANCE: f64 = 0.6;
+/// Distance of an enemy base from its planet's centre, in planet radii.
+const BASE_ALTITUDE: f32 = 1.5;
+const TURRET_HEALTH: f32 = 60.0;
+/// In model units, scaled with the station.
+const TURRET_RADIUS: f32 = 1.0;
+const TURRET_RANGE: f32 = 900.0;
+/// Gap between a turret's collider and where its bullets appear, so they don't start
+/// touching the turret or the station right under it.
+const MUZZLE_CLEARANCE: f32 = 2.0;
+
+/// A box making up part of a station's collider, in the part's model units.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartCollider {
+    pub center: Vec3,
+    pub half_extents: Vec3,
+}
+
+/// One kit model placed in a station.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationPart {
+    pub model: String,
+    pub offset: Vec3,
+    /// Degrees around the station's up axis.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub collider: Option<PartCollider>,
+}
+
+impl StationPart {
+    fn transform(&self) -> Transform {
+        Transform::from_translation(self.offset).with_rotation(Quat::from_rotation_y(self.rotation.to_radians()))
+    }
+}
+
+/// How a station is put together. Offsets and colliders are in model units and scaled with
+/// the whole station.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationLayout {
+    pub scale: f32,
+    pub parts: Vec<StationPart>,
+    /// Parts that shoot at the player when the station is an enemy base.
+    #[serde(default)]
+    pub turrets: Vec<StationPart>,
+}
+
+/// All station layouts and which are used for what, loaded from `assets/stations.ron`.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct StationLayouts {
+    pub layouts: HashMap<String, StationLayout>,
+    pub friendly_hubs: Vec<String>,
+    pub enemy_bases: Vec<String>,
+}
+
+impl Default for StationLayouts {
+    fn default() -> Self {
+        let part = |model: &str, offset: Vec3, half_extents: Vec3| StationPart {
+            model: model.to_string(),
+            offset,
+            rotation: 0.0,
+            collider: Some(PartCollider {
+                center: Vec3::Y * half_extents.y,
+                half_extents,
+            }),
+        };
+        StationLayouts {
+            layouts: HashMap::from([
+                ("Hub".to_string(), StationLayout {
+                    scale: 10.0,
+                    parts: vec![
+                        part("Base Large", Vec3::ZERO, Vec3::new(4.0, 2.5, 4.0)),
+                        part("Geodesic Dome", Vec3::Y * 5.0, Vec3::new(4.0, 2.5, 4.0)),
+                    ],
+                    turrets: Vec::new(),
+                }),
+                ("Base".to_string(), StationLayout {
+                    scale: 10.0,
+                    parts: vec![part("House Cylinder", Vec3::ZERO, Vec3::new(3.0, 2.0, 3.0))],
+                    turrets: vec![part("Connector", Vec3::Y * 4.0, Vec3::ONE)],
+                }),
+            ]),
+            friendly_hubs: vec!["Hub".to_string()],
+            enemy_bases: vec!["Base".to_string()],
+        }
+    }
+}
+
+impl StationLayouts {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let layouts: StationLayouts = load_ron(path)?;
+        if let Some(name) = layouts.friendly_hubs
+            .iter()
+            .chain(&layouts.enemy_bases)
+            .find(|name| !layouts.layouts.contains_key(*name)) {
+            return Err(format!("{}: unknown station layout {:?}", path.display(), name));
+        }
+        let unknown_model = layouts.layouts
+            .values()
+            .flat_map(|layout| layout.parts.iter().chain(&layout.turrets))
+            .find(|part| !STATION_PARTS.contains(&part.model.as_str()));
+        match unknown_model {
+            Some(part) => Err(format!("{}: unknown station part {:?}", path.display(), part.model)),
+            None => Ok(layouts),
+        }
+    }
+
+    fn pick(&self, names: &[String], rng: &mut impl Rng) -> Option<&StationLayout> {
+        if names.is_empty() {
+            return None;
+        }
+        self.layouts.get(&names[rng.random_range(0..names.len())])
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Station;
+
+/// Gun on an enemy base, firing at the player whenever in range.
+#[derive(Component, Debug)]
+pub struct Turret;
+
+pub struct StationPlugin;
+
+impl Plugin for StationPlugin {
+    fn build(&self, app: &mut App) {
+        let layouts = StationLayouts::load(Path::new(STATIONS_PATH)).unwrap_or_else(|error| {
+            warn!("Using default stations, could not load {}", error);
+            StationLayouts::default()
+        });
+        app.insert_resource(layouts)
+            .add_systems(OnEnter(InGame), spawn_stations.after(spawn_star_system))
+            .add_systems(Update, (repair_at_hubs, fire_turrets.in_set(BulletSet::Fire)).run_if(in_state(AppState::Playing)));
+    }
+}
+
+/// Puts a friendly hub near where the player starts and enemy bases above some of the planets.
+fn spawn_stations(
+    mut commands: Commands,
+    space_kit: Res<SpaceKit>,
+    layouts: Res<StationLayouts>,
+    weapons: Res<WeaponDefinitions>,
+    system: Res<StarSystem>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream(RngStream::World);
+    if let Some(layout) = layouts.pick(&layouts.friendly_hubs, rng) {
+        let position = Quat::from_rotation_y(rng.random_range(0.0..TAU)) * Vec3::X * HUB_DISTANCE;
+        spawn_station(&mut commands, &space_kit, &weapons, layout, Faction::Player, Transform::from_translation(position));
+    }
+    for (center, surface) in system.planets.iter() {
+        if !rng.random_bool(BASE_CHANCE) {
+            continue;
+        }
+        let Some(layout) = layouts.pick(&layouts.enemy_bases, rng) else {
+            break;
+        };
+        let direction = random_direction(rng);
+        // Hold position above the planet, station floor facing it
+        let transform = Transform::from_translation(*center + direction * *surface * BASE_ALTITUDE)
+            .with_rotation(Quat::from_rotation_arc(Vec3::Y, direction));
+        spawn_station(&mut commands, &space_kit, &weapons, layout, Faction::Enemy, transform);
+    }
+}
+
+/// Assembles a station from its layout. The station is one fixed body with a compound
+/// collider, enemy bases also get a separate body for each turret.
+fn spawn_station(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    weapons: &WeaponDefinitions,
+    layout: &StationLayout,
+    faction: Faction,
+    transform: Transform,
+) {
+    let transform = transform.with_scale(Vec3::splat(layout.scale));
+    let shapes: Vec<(Vec3, Quat, Collider)> = layout.parts
+        .iter()
+        .filter_map(|part| {
+            let collider = part.collider.as_ref()?;
+            let part_transform = part.transform();
+            let half = collider.half_extents;
+            Some((
+                part_transform.transform_point(collider.center),
+                part_transform.rotation,
+                Collider::cuboid(half.x, half.y, half.z),
+            ))
+        })
+        .collect();
+
+    commands.spawn((
+        transform,
+        Visibility::default(),
+        RigidBody::Fixed,
+        Collider::compound(shapes),
+        Station,
+        faction,
+        StateScoped(InGame),
+    )).with_children(|parent| {
+        for part in &layout.parts {
+            parent.spawn((
+                SceneRoot(space_kit.station_parts.get(&part.model).cloned().unwrap_or_default()),
+                part.transform(),
+            ));
+        }
+    });
+
+    if faction == Faction::Enemy {
+        for turret in &layout.turrets {
+            commands.spawn((
+                SceneRoot(space_kit.station_parts.get(&turret.model).cloned().unwrap_or_default()),
+                transform * turret.transform(),
+                RigidBody::Fixed,
+                Collider::ball(TURRET_RADIUS),
+                Turret,
+                faction,
+                Health::new(TURRET_HEALTH),
+                WeaponSlot::new(weapons.enemy_loadout.clone()),
+                StateScoped(InGame),
+            ));
+        }
+    }
+}
+
+fn repair_at_hubs(
+    time: Res<Time>,
+    station_query: Query<(&Transform, &Faction), With<Station>>,
+    mut spaceship_query: Query<(&Transform, &mut Health), (With<SpaceShip>, Without<Destroyed>)>,
+) {
+    let Ok((spaceship_transform, mut health)) = spaceship_query.get_single_mut() else {
+        return;
+    };
+    let docked = station_query.iter().any(|(transform, faction)| {
+        *faction == Faction::Player
+            && transform.translation.distance(spaceship_transform.translation) < HUB_REPAIR_RANGE
+    });
+    if docked {
+        health.current = (health.current + HUB_REPAIR_RATE * time.delta_secs()).min(health.max);
+    }
+}
+
+fn fire_turrets(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    bullet_assets: Res<BulletAssets>,
+    time: Res<Time>,
+    weapons: Res<WeaponDefinitions>,
+    mut rng: ResMut<GameRng>,
+    spaceship_query: Query<&Transform, (With<SpaceShip>, Without<Turret>)>,
+    mut turret_query: Query<(Entity, &mut Transform, &mut WeaponSlot), With<Turret>>,
+) {
+    let Ok(spaceship_transform) = spaceship_query.get_single() else {
+        return;
+    };
+    for (turret_entity, mut turret_transform, mut slot) in turret_query.iter_mut() {
+        let Some((weapon, visual)) = slot.active_weapon().and_then(|name| weapons.get(name).zip(bullet_assets.get(name))) else {
+            continue;
+        };
+        let offset = spaceship_transform.translation - turret_transform.translation;
+        let in_range = offset.length() < TURRET_RANGE;
+        if in_range {
+            // Turn the gun towards the player, its barrel along +Z
+            turret_transform.rotation = Quat::from_rotation_arc(Vec3::Z, offset.normalize());
+        }
+        if slot.fire(time.delta_secs(), in_range, weapon) {
+            let barrel = turret_transform.rotation * Vec3::Z;
+            let radius = TURRET_RADIUS * turret_transform.scale.max_element();
+            fire_weapon(
+                &mut commands,
+                &mut pool,
+                visual,
+                rng.stream(RngStream::TurretWeapons),
+                weapon,
+                turret_entity,
+                Faction::Enemy,
+                turret_transform.translation + barrel * (radius + MUZZLE_CLEARANCE),
+                turret_transform.rotation,
+                None,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::load_from_temp_file;
+
+    fn load(layouts: &StationLayouts, name: &str) -> Result<StationLayouts, String> {
+        load_from_temp_file(layouts, name, StationLayouts::load)
+    }
+
+    #[test]
+    fn shipped_layouts_load() {
+        let layouts = StationLayouts::load(Path::new(STATIONS_PATH)).unwrap();
+        assert!(!layouts.friendly_hubs.is_empty());
+        assert!(!layouts.enemy_bases.is_empty());
+    }
+
+    #[test]
+    fn default_layouts_load() {
+        assert_eq!(load(&StationLayouts::default(), "stations_defaults_test.ron").unwrap().layouts, StationLayouts::default().layouts);
+    }
+
+    #[test]
+    fn load_rejects_unknown_layout() {
+        let mut layouts = StationLayouts::default();
+        layouts.enemy_bases.push("Death Star".to_string());
+        assert!(load(&layouts, "stations_unknown_layout_test.ron").is_err());
+    }
+
+    #[test]
+    fn load_rejects_unknown_model() {
+        let mut layouts = StationLayouts::default();
+        layouts.layouts.get_mut("Base").unwrap().turrets[0].model = "Laser Cannon".to_string();
+        assert!(load(&layouts, "stations_unknown_model_test.ron").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::time::Duration;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use rand::Rng;
+use crate::asset::SpaceKit;
+use crate::enemy::{spawn_enemy, Enemy};
+use crate::game::{AppState, InGame};
+use crate::mech::{spawn_mech, Mech};
+use crate::rng::{random_direction, GameRng, RngStream};
+use crate::spaceship::SpaceShip;
+use crate::weapon::WeaponDefinitions;
+
+const INTERMISSION: Duration = Duration::from_secs(5);
+const GROUP_INTERVAL: Duration = Duration::from_millis(1500);
+const ANNOUNCEMENT_DURATION: Duration = Duration::from_secs(3);
+const SPAWN_MIN_DISTANCE: f32 = 250.0;
+const SPAWN_MAX_DISTANCE: f32 = 600.0;
+const GROUP_SPREAD: f32 = 40.0;
+
+/// How many of each hostile a wave sends and how many arrive together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveComposition {
+    pub enemies: u32,
+    pub mechs: u32,
+    pub group_size: u32,
+}
+
+impl WaveComposition {
+    pub fn for_wave(wave: u32) -> Self {
+        WaveComposition {
+            enemies: wave * 2 - 1,
+            mechs: 6 + wave * 4,
+            group_size: 3 + wave / 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WavePhase {
+    Intermission,
+    Spawning,
+    Fighting,
+}
+
+#[derive(Resource, Debug)]
+pub struct WaveDirector {
+    pub wave: u32,
+    phase: WavePhase,
+    pending_enemies: u32,
+    pending_mechs: u32,
+    group_size: u32,
+    timer: Timer,
+}
+
+impl Default for WaveDirector {
+    fn default() -> Self {
+        WaveDirector {
+            wave: 0,
+            phase: WavePhase::Intermission,
+            pending_enemies: 0,
+            pending_mechs: 0,
+            group_size: 0,
+            timer: Timer::new(INTERMISSION, TimerMode::Once),
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct WaveStarted {
+    pub wave: u32,
+}
+
+#[derive(Event, Debug)]
+pub struct WaveCleared {
+    pub wave: u32,
+}
+
+#[derive(Component)]
+struct WaveText;
+
+#[derive(Component)]
+struct WaveAnnouncement(Timer);
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaveDirector>()
+            .add_event::<WaveStarted>()
+            .add_event::<WaveCleared>()
+            .add_systems(OnEnter(InGame), (reset_waves, spawn_wave_ui))
+            .add_systems(Update, (run_waves, announce_waves, update_wave_text).chain().run_if(in_state(AppState::Playing)));
+    }
+}
+
+fn reset_waves(mut director: ResMut<WaveDirector>) {
+    *director = WaveDirector::default();
+}
+
+fn run_waves(
+    mut commands: Commands,
+    space_kit: Res<SpaceKit>,
+    weapons: Res<WeaponDefinitions>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    time: Res<Time>,
+    mut director: ResMut<WaveDirector>,
+    mut rng: ResMut<GameRng>,
+    mut started_events: EventWriter<WaveStarted>,
+    mut cleared_events: EventWriter<WaveCleared>,
+    spaceship_query: Query<&Transform, With<SpaceShip>>,
+    hostile_query: Query<(), Or<(With<Enemy>, With<Mech>)>>,
+) {
+    let Ok(spaceship_transform) = spaceship_query.get_single() else {
+        return;
+    };
+    match director.phase {
+        WavePhase::Intermission => {
+            if director.timer.tick(time.delta()).just_finished() {
+                director.wave += 1;
+                let composition = WaveComposition::for_wave(director.wave);
+                director.pending_enemies = composition.enemies;
+                director.pending_mechs = composition.mechs;
+                director.group_size = composition.group_size;
+                director.phase = WavePhase::Spawning;
+                director.timer = Timer::new(GROUP_INTERVAL, TimerMode::Repeating);
+                started_events.send(WaveStarted { wave: director.wave });
+                spawn_group(&mut commands, &space_kit, &weapons, &mut meshes, rng.stream(RngStream::Waves), &mut director, spaceship_transform.translation);
+            }
+        }
+        WavePhase::Spawning => {
+            if director.timer.tick(time.delta()).just_finished() {
+                spawn_group(&mut commands, &space_kit, &weapons, &mut meshes, rng.stream(RngStream::Waves), &mut director, spaceship_transform.translation);
+            }
+            if director.pending_enemies == 0 && director.pending_mechs == 0 {
+                director.phase = WavePhase::Fighting;
+            }
+        }
+        WavePhase::Fighting => {
+            if hostile_query.is_empty() {
+                cleared_events.send(WaveCleared { wave: director.wave });
+                director.phase = WavePhase::Intermission;
+                director.timer = Timer::new(INTERMISSION, TimerMode::Once);
+            }
+        }
+    }
+}
+
+/// Spawns the next group of the current wave at a random point in a shell around the player.
+/// Enemies are sent first so later groups of a wave lean towards mechs.
+fn spawn_group(
+    commands: &mut Commands,
+    space_kit: &SpaceKit,
+    weapons: &WeaponDefinitions,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    rng: &mut impl Rng,
+    director: &mut WaveDirector,
+    player_position: Vec3,
+) {
+    let direction = random_direction(rng);
+    let center = player_position + direction * rng.random_range(SPAWN_MIN_DISTANCE..SPAWN_MAX_DISTANCE);
+
+    for _ in 0..director.group_size {
+        let position = center + Vec3::new(
+            rng.random_range(-GROUP_SPREAD..GROUP_SPREAD),
+            rng.random_range(-GROUP_SPREAD..GROUP_SPREAD),
+            rng.random_range(-GROUP_SPREAD..GROUP_SPREAD),
+        );
+        let transform = Transform::from_translation(position).looking_at(player_position, Vec3::Y);
+        if director.pending_enemies > 0 {
+            director.pending_enemies -= 1;
+            spawn_enemy(commands, space_kit, meshes, weapons, transform);
+        } else if director.pending_mechs > 0 {
+            director.pending_mechs -= 1;
+            spawn_mech(commands, space_kit, meshes, rng, transform);
+        } else {
+            break;
+        }
+    }
+}
+
+fn spawn_wave_ui(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextLayout::new_with_justify(JustifyText::Right),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(0.0),
+            right: Val::Px(0.0),
+            ..default()
+        },
+        WaveText,
+        StateScoped(InGame),
+    ));
+}
+
+fn announce_waves(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut started_events: EventReader<WaveStarted>,
+    mut cleared_events: EventReader<WaveCleared>,
+    mut announcement_query: Query<(Entity, &mut WaveAnnouncement)>,
+) {
+    for (entity, mut announcement) in announcement_query.iter_mut() {
+        if announcement.0.tick(time.delta()).just_finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let messages = cleared_events.read()
+        .map(|cleared| format!("Wave {} cleared", cleared.wave))
+        .chain(started_events.read().map(|started| format!("Wave {}", started.wave)));
+    for message in messages {
+        commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(30.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            WaveAnnouncement(Timer::new(ANNOUNCEMENT_DURATION, TimerMode::Once)),
+            StateScoped(InGame),
+        )).with_children(|parent| {
+            parent.spawn((
+                Text(message),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+            ));
+        });
+    }
+}
+
+fn update_wave_text(
+    director: Res<WaveDirector>,
+    hostile_query: Query<(), Or<(With<Enemy>, With<Mech>)>>,
+    mut wave_text: Query<&mut Text, With<WaveText>>,
+) {
+    if let Ok(mut text) = wave_text.get_single_mut() {
+        text.0 = format!("Wave: {}  Hostiles: {}", director.wave, hostile_query.iter().count());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_wave_is_small() {
+        assert_eq!(WaveComposition::for_wave(1), WaveComposition {
+            enemies: 1,
+            mechs: 10,
+            group_size: 3,
+        });
+    }
+
+    #[test]
+    fn waves_escalate() {
+        for wave in 1..20 {
+            let current = WaveComposition::for_wave(wave);
+            let next = WaveComposition::for_wave(wave + 1);
+            assert!(next.enemies > current.enemies);
+            assert!(next.mechs > current.mechs);
+            assert!(next.group_size >= current.group_size);
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::path::Path;
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::asset::load_ron;
+use crate::bullet::{spawn_bullet, BulletPool, BulletVisual};
+use crate::faction::Faction;
+
+pub const WEAPONS_PATH: &str = "assets/weapons.ron";
+
+/// How a weapon's projectiles look.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectileVisual {
+    pub color: (f32, f32, f32),
+    /// Emissive colour, values above 1 glow.
+    pub glow: (f32, f32, f32),
+    pub radius: f32,
+}
+
+/// Everything that makes one weapon different from another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponDefinition {
+    /// Shots per second.
+    pub fire_rate: f32,
+    pub projectile_speed: f32,
+    pub damage: f32,
+    /// Half angle in degrees of the cone each projectile is randomly fired into.
+    pub spread: f32,
+    /// Where projectiles leave the ship, relative to its centre with +Z pointing where the
+    /// weapon fires. One projectile is fired from every muzzle per shot.
+    pub muzzles: Vec<Vec3>,
+    /// Seconds before a projectile that hit nothing disappears.
+    pub lifetime: f32,
+    pub visual: ProjectileVisual,
+}
+
+/// All weapons and who carries which, loaded from `assets/weapons.ron`.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponDefinitions {
+    pub weapons: HashMap<String, WeaponDefinition>,
+    pub player_loadout: Vec<String>,
+    pub enemy_loadout: Vec<String>,
+}
+
+impl Default for WeaponDefinitions {
+    fn default() -> Self {
+        let red = ProjectileVisual {
+            color: (1.0, 0.0, 0.0),
+            glow: (10.0, 0.1, 0.1),
+            radius: 0.3,
+        };
+        WeaponDefinitions {
+            weapons: HashMap::from([
+                ("Blaster".to_string(), WeaponDefinition {
+                    fire_rate: 10.0,
+                    projectile_speed: 700.0,
+                    damage: 10.0,
+                    spread: 0.0,
+                    muzzles: vec![Vec3::new(0.0, 0.0, 15.0), Vec3::new(-5.0, 0.0, 0.0), Vec3::new(5.0, 0.0, 0.0)],
+                    lifetime: 5.0,
+                    visual: red.clone(),
+                }),
+                ("Enemy Blaster".to_string(), WeaponDefinition {
+                    fire_rate: 1.0,
+                    projectile_speed: 100.0,
+                    damage: 10.0,
+                    spread: 0.0,
+                    muzzles: vec![Vec3::new(0.0, 0.0, 15.0)],
+                    lifetime: 5.0,
+                    visual: red,
+                }),
+            ]),
+            player_loadout: vec!["Blaster".to_string()],
+            enemy_loadout: vec!["Enemy Blaster".to_string()],
+        }
+    }
+}
+
+impl WeaponDefinitions {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let definitions: WeaponDefinitions = load_ron(path)?;
+        let missing = definitions.player_loadout
+            .iter()
+            .chain(&definitions.enemy_loadout)
+            .find(|name| !definitions.weapons.contains_key(*name));
+        if let Some(name) = missing {
+            return Err(format!("{}: loadout uses unknown weapon {:?}", path.display(), name));
+        }
+        match definitions.weapons.iter().find(|(_, weapon)| weapon.fire_rate <= 0.0) {
+            Some((name, _)) => Err(format!("{}: weapon {:?} needs a fire_rate above 0", path.display(), name)),
+            None => Ok(definitions),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WeaponDefinition> {
+        self.weapons.get(name)
+    }
+}
+
+/// The weapons a ship carries, which one is selected and how long until it can fire again.
+#[derive(Component, Debug)]
+pub struct WeaponSlot {
+    pub loadout: Vec<String>,
+    pub active: usize,
+    cooldown: f32,
+}
+
+impl WeaponSlot {
+    pub fn new(loadout: Vec<String>) -> Self {
+        WeaponSlot {
+            loadout,
+            active: 0,
+            cooldown: 0.0,
+        }
+    }
+
+    pub fn active_weapon(&self) -> Option<&str> {
+        self.loadout.get(self.active).map(String::as_str)
+    }
+
+    /// Selects the weapon `step` places further along the loadout, wrapping around.
+    pub fn cycle(&mut self, step: i32) {
+        if !self.loadout.is_empty() {
+            self.active = (self.active as i32 + step).rem_euclid(self.loadout.len() as i32) as usize;
+        }
+    }
+
+    /// Counts the cooldown down by `delta` seconds. Returns true, and restarts the cooldown,
+    /// when the trigger is held and the weapon is ready.
+    pub fn fire(&mut self, delta: f32, trigger: bool, weapon: &WeaponDefinition) -> bool {
+        self.cooldown = (self.cooldown - delta).max(0.0);
+        if trigger && self.cooldown == 0.0 {
+            self.cooldown = 1.0 / weapon.fire_rate;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        let definitions = WeaponDefinitions::load(Path::new(WEAPONS_PATH)).unwrap_or_else(|error| {
+            warn!("Using default weapons, could not load {}", error);
+            WeaponDefinitions::default()
+        });
+        app.insert_resource(definitions);
+    }
+}
+
+/// Fires one shot of `weapon` from every muzzle. `rotation` turns the weapon's +Z into the
+/// world direction it fires in. With a `target`, muzzles converge on it instead of firing
+/// parallel.
+pub fn fire_weapon(
+    commands: &mut Commands,
+    pool: &mut BulletPool,
+    visual: &BulletVisual,
+    rng: &mut impl Rng,
+    weapon: &WeaponDefinition,
+    owner: Entity,
+    faction: Faction,
+    origin: Vec3,
+    rotation: Quat,
+    target: Option<Vec3>,
+) {
+    let forward = rotation * Vec3::Z;
+    for muzzle in &weapon.muzzles {
+        let position = origin + rotation * *muzzle;
+        let direction = match target {
+            // Never shoot backwards at a point behind the guns
+            Some(target) if (target - position).dot(forward) > 0.0 => (target - position).normalize(),
+            _ => forward,
+        };
+        spawn_bullet(
+            commands,
+            pool,
+            visual,
+            Velocity {
+                linvel: scatter(direction, weapon.spread, rng) * weapon.projectile_speed,
+                ..default()
+            },
+            Transform::from_translation(position),
+            owner,
+            faction,
+            weapon,
+        );
+    }
+}
+
+/// Tilts `direction` by a random angle of up to `spread` degrees.
+fn scatter(direction: Vec3, spread: f32, rng: &mut impl Rng) -> Vec3 {
+    if spread <= 0.0 {
+        return direction;
+    }
+    let angle = rng.random_range(0.0..spread.to_radians());
+    let around = rng.random_range(0.0..TAU);
+    let axis = Quat::from_axis_angle(direction, around) * direction.any_orthonormal_vector();
+    Quat::from_axis_angle(axis, angle) * direction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::load_from_temp_file;
+
+    fn blaster() -> WeaponDefinition {
+        WeaponDefinitions::default().weapons["Blaster"].clone()
+    }
+
+    #[test]
+    fn fire_waits_for_cooldown() {
+        let weapon = blaster();
+        let mut slot = WeaponSlot::new(vec!["Blaster".to_string()]);
+        assert!(!slot.fire(0.0, false, &weapon));
+        assert!(slot.fire(0.0, true, &weapon));
+        // Ten shots per second, so the next one is ready after 0.1 seconds
+        assert!(!slot.fire(0.05, true, &weapon));
+        assert!(slot.fire(0.05, true, &weapon));
+    }
+
+    #[test]
+    fn cycle_wraps_around() {
+        let mut slot = WeaponSlot::new(vec!["A".to_string(), "B".to_string(), "C".to_string()]);
+        slot.cycle(1);
+        assert_eq!(slot.active_weapon(), Some("B"));
+        slot.cycle(2);
+        assert_eq!(slot.active_weapon(), Some("A"));
+        slot.cycle(-1);
+        assert_eq!(slot.active_weapon(), Some("C"));
+    }
+
+    #[test]
+    fn cycle_with_empty_loadout() {
+        let mut slot = WeaponSlot::new(Vec::new());
+        slot.cycle(1);
+        assert_eq!(slot.active_weapon(), None);
+    }
+
+    fn load(definitions: &WeaponDefinitions, name: &str) -> Result<WeaponDefinitions, String> {
+        load_from_temp_file(definitions, name, WeaponDefinitions::load)
+    }
+
+    #[test]
+    fn load_accepts_defaults() {
+        assert!(load(&WeaponDefinitions::default(), "weapons_defaults_test.ron").is_ok());
+    }
+
+    #[test]
+    fn load_rejects_unknown_loadout_weapon() {
+        let mut definitions = WeaponDefinitions::default();
+        definitions.player_loadout.push("Railgun".to_string());
+        assert!(load(&definitions, "weapons_unknown_test.ron").is_err());
+    }
+
+    #[test]
+    fn load_rejects_fire_rate_of_zero() {
+        let mut definitions = WeaponDefinitions::default();
+        definitions.weapons.get_mut("Blaster").unwrap().fire_rate = 0.0;
+        assert!(load(&definitions, "weapons_fire_rate_test.ron").is_err());
+    }
+}